@@ -3,4 +3,13 @@ fn main() {
     println!("cargo:rustc-link-arg-bins=-Tlink.x");
     #[cfg(feature = "defmt")]
     println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
+
+    // Capture replayed by the `replay` feature, the checked-in one unless
+    // `CRANKSHAFT_REPLAY` names another.
+    println!("cargo:rerun-if-env-changed=CRANKSHAFT_REPLAY");
+    let capture = std::env::var("CRANKSHAFT_REPLAY").unwrap_or_else(|_| {
+        let root = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        format!("{root}/fixtures/sixty_minus_two.in")
+    });
+    println!("cargo:rustc-env=CRANKSHAFT_REPLAY_CAPTURE={capture}");
 }
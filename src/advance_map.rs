@@ -0,0 +1,136 @@
+/// Ignition advance in degrees indexed by RPM and load.
+///
+/// Values between breakpoints are interpolated bilinearly, values outside the
+/// axes are clamped to the nearest edge of the map. A NaN input, as from a
+/// sensor fault, reads as the lowest breakpoint of its axis.
+pub struct AdvanceMap<const R: usize, const L: usize> {
+    rpm: [f64; R],
+    load: [f64; L],
+    // advance[r][l] is the advance at rpm[r] and load[l].
+    advance: [[f64; L]; R],
+}
+
+impl<const R: usize, const L: usize> AdvanceMap<R, L> {
+    /// Both axes must be non-empty and strictly increasing.
+    pub const fn new(rpm: [f64; R], load: [f64; L], advance: [[f64; L]; R]) -> Self {
+        assert!(
+            is_strictly_increasing(&rpm),
+            "RPM axis must be strictly increasing"
+        );
+        assert!(
+            is_strictly_increasing(&load),
+            "Load axis must be strictly increasing"
+        );
+
+        Self { rpm, load, advance }
+    }
+
+    pub fn lookup(&self, rpm: f64, load: f64) -> f64 {
+        let (r, r_frac) = locate(&self.rpm, rpm);
+        let (l, l_frac) = locate(&self.load, load);
+
+        // Neighbouring breakpoints, collapsed onto the edge for one-point axes.
+        let r_next = (r + 1).min(R - 1);
+        let l_next = (l + 1).min(L - 1);
+
+        let low = lerp(self.advance[r][l], self.advance[r][l_next], l_frac);
+        let high = lerp(
+            self.advance[r_next][l],
+            self.advance[r_next][l_next],
+            l_frac,
+        );
+
+        lerp(low, high, r_frac)
+    }
+}
+
+const fn is_strictly_increasing(axis: &[f64]) -> bool {
+    if axis.is_empty() {
+        return false;
+    }
+
+    let mut i = 1;
+    while i < axis.len() {
+        if axis[i] <= axis[i - 1] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+// Index of the breakpoint at or below `x` and the fraction of the way to the next one.
+fn locate(axis: &[f64], x: f64) -> (usize, f64) {
+    let last = axis.len() - 1;
+
+    if x.is_nan() || x <= axis[0] {
+        return (0, 0.0);
+    }
+    if x >= axis[last] {
+        return (last, 0.0);
+    }
+
+    let i = axis.partition_point(|&breakpoint| breakpoint <= x) - 1;
+    (i, (x - axis[i]) / (axis[i + 1] - axis[i]))
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    const MAP: AdvanceMap<3, 2> = AdvanceMap::new(
+        [1000.0, 2000.0, 4000.0],
+        [20.0, 80.0],
+        [
+            [10.0, 5.0],
+            [20.0, 12.0],
+            [30.0, 20.0],
+        ],
+    );
+
+    #[test]
+    fn breakpoints_return_the_map_values() {
+        assert_eq!(MAP.lookup(1000.0, 20.0), 10.0);
+        assert_eq!(MAP.lookup(2000.0, 80.0), 12.0);
+        assert_eq!(MAP.lookup(4000.0, 20.0), 30.0);
+    }
+
+    #[test]
+    fn cell_centers_average_the_corners() {
+        assert_eq!(MAP.lookup(1500.0, 50.0), (10.0 + 5.0 + 20.0 + 12.0) / 4.0);
+        assert_eq!(MAP.lookup(3000.0, 50.0), (20.0 + 12.0 + 30.0 + 20.0) / 4.0);
+    }
+
+    #[test]
+    fn edges_interpolate_along_one_axis() {
+        assert_eq!(MAP.lookup(1000.0, 50.0), 7.5);
+        assert_eq!(MAP.lookup(3000.0, 80.0), 16.0);
+    }
+
+    #[test]
+    fn out_of_range_inputs_clamp_to_the_edges() {
+        assert_eq!(MAP.lookup(500.0, 0.0), 10.0);
+        assert_eq!(MAP.lookup(9000.0, 100.0), 20.0);
+        assert_eq!(MAP.lookup(f64::INFINITY, f64::NEG_INFINITY), 30.0);
+        assert_eq!(MAP.lookup(1500.0, 100.0), 8.5);
+    }
+
+    #[test]
+    fn nan_reads_as_the_lowest_breakpoint() {
+        assert_eq!(MAP.lookup(f64::NAN, 80.0), 5.0);
+        assert_eq!(MAP.lookup(2000.0, f64::NAN), 20.0);
+    }
+
+    #[test]
+    fn single_breakpoint_axes_are_constant() {
+        let map = AdvanceMap::new([3000.0], [50.0], [[15.0]]);
+        assert_eq!(map.lookup(1000.0, 10.0), 15.0);
+        assert_eq!(map.lookup(3000.0, 50.0), 15.0);
+        assert_eq!(map.lookup(f64::NAN, 90.0), 15.0);
+    }
+}
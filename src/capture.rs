@@ -0,0 +1,159 @@
+use embassy_stm32::interrupt::typelevel::Binding;
+use embassy_stm32::time::Hertz;
+#[cfg(not(feature = "polled-capture"))]
+use embassy_stm32::timer::input_capture::InputCapture;
+use embassy_stm32::timer::input_capture::{CapturePin, Ch1, Ch2, Ch3, Ch4};
+use embassy_stm32::timer::low_level::CountingMode;
+use embassy_stm32::timer::{
+    CaptureCompareInterruptHandler, Channel, CoreInstance, GeneralInstance4Channel, TimerBits,
+};
+use embassy_stm32::Peripheral;
+#[cfg(feature = "polled-capture")]
+use {
+    embassy_futures::yield_now,
+    embassy_stm32::timer::low_level::{FilterValue, InputCaptureMode, InputTISelection, Timer},
+};
+
+use crate::tick::Tick;
+
+// Capture pins handed to `InputCapture`, one slot per channel.
+type CapturePins<'d, T> = (
+    Option<CapturePin<'d, T, Ch1>>,
+    Option<CapturePin<'d, T, Ch2>>,
+    Option<CapturePin<'d, T, Ch3>>,
+    Option<CapturePin<'d, T, Ch4>>,
+);
+
+/// Channel marker of a [`CapturePin`], tying it to its timer channel.
+pub trait CaptureChannel: Sized {
+    const CHANNEL: Channel;
+
+    #[doc(hidden)]
+    fn into_slot<T>(pin: CapturePin<'_, T, Self>) -> CapturePins<'_, T>;
+}
+
+impl CaptureChannel for Ch1 {
+    const CHANNEL: Channel = Channel::Ch1;
+
+    fn into_slot<T>(pin: CapturePin<'_, T, Self>) -> CapturePins<'_, T> {
+        (Some(pin), None, None, None)
+    }
+}
+
+impl CaptureChannel for Ch2 {
+    const CHANNEL: Channel = Channel::Ch2;
+
+    fn into_slot<T>(pin: CapturePin<'_, T, Self>) -> CapturePins<'_, T> {
+        (None, Some(pin), None, None)
+    }
+}
+
+impl CaptureChannel for Ch3 {
+    const CHANNEL: Channel = Channel::Ch3;
+
+    fn into_slot<T>(pin: CapturePin<'_, T, Self>) -> CapturePins<'_, T> {
+        (None, None, Some(pin), None)
+    }
+}
+
+impl CaptureChannel for Ch4 {
+    const CHANNEL: Channel = Channel::Ch4;
+
+    fn into_slot<T>(pin: CapturePin<'_, T, Self>) -> CapturePins<'_, T> {
+        (None, None, None, Some(pin))
+    }
+}
+
+/// Rising edges of the crank sensor, captured by one channel of a timer.
+///
+/// By default the task waiting on `next_tick` is woken by the capture
+/// interrupt. With the `polled-capture` feature it polls the capture flag
+/// instead, yielding to the executor in between, for setups where the capture
+/// interrupt is needed for something else. The counter latches the edge in
+/// hardware either way, polling only delays when the tick is handed over.
+pub struct Capture<'d, T: GeneralInstance4Channel> {
+    #[cfg(not(feature = "polled-capture"))]
+    timer: InputCapture<'d, T>,
+    #[cfg(feature = "polled-capture")]
+    timer: Timer<'d, T>,
+    channel: Channel,
+}
+
+impl<'d, T: GeneralInstance4Channel> Capture<'d, T> {
+    /// Width of the counter of the timer, to be given to
+    /// `TriggerWheel::with_timer_bits`. The counter wraps every 65 ms at 1 MHz
+    /// on a 16 bits timer.
+    pub const TIMER_BITS: u8 = match <T as CoreInstance>::BITS {
+        TimerBits::Bits16 => 16,
+        TimerBits::Bits32 => 32,
+    };
+
+    /// Runs the counter of `tim` at `freq`, capturing the rising edges on `pin`.
+    ///
+    /// The interrupt binding is only used by the interrupt backend, it is
+    /// asked for either way so that switching backends doesn't touch the
+    /// application.
+    pub fn new<C: CaptureChannel>(
+        tim: impl Peripheral<P = T> + 'd,
+        pin: CapturePin<'d, T, C>,
+        irq: impl Binding<T::CaptureCompareInterrupt, CaptureCompareInterruptHandler<T>> + 'd,
+        freq: Hertz,
+    ) -> Self {
+        #[cfg(not(feature = "polled-capture"))]
+        let timer = {
+            let (ch1, ch2, ch3, ch4) = C::into_slot(pin);
+            InputCapture::new(
+                tim,
+                ch1,
+                ch2,
+                ch3,
+                ch4,
+                irq,
+                freq,
+                CountingMode::EdgeAlignedUp,
+            )
+        };
+
+        #[cfg(feature = "polled-capture")]
+        let timer = {
+            let _ = (pin, irq);
+            let mut timer = Timer::new(tim);
+            timer.set_counting_mode(CountingMode::EdgeAlignedUp);
+            timer.set_tick_freq(freq);
+            // Required for advanced timers.
+            timer.enable_outputs();
+            timer.start();
+
+            let channel = C::CHANNEL;
+            timer.set_input_ti_selection(channel, InputTISelection::Normal);
+            timer.set_input_capture_filter(channel, FilterValue::NO_FILTER);
+            timer.set_input_capture_mode(channel, InputCaptureMode::Rising);
+            timer.set_input_capture_prescaler(channel, 0);
+            timer.enable_channel(channel, true);
+            timer
+        };
+
+        Self {
+            timer,
+            channel: C::CHANNEL,
+        }
+    }
+
+    /// Waits for the next rising edge and returns the counter value it was
+    /// captured at.
+    #[cfg(not(feature = "polled-capture"))]
+    pub async fn next_tick(&mut self) -> Tick {
+        Tick::from_ticks(self.timer.wait_for_rising_edge(self.channel).await)
+    }
+
+    /// Waits for the next rising edge and returns the counter value it was
+    /// captured at.
+    #[cfg(feature = "polled-capture")]
+    pub async fn next_tick(&mut self) -> Tick {
+        // Reading the captured value clears the flag.
+        while !self.timer.get_input_interrupt(self.channel) {
+            yield_now().await;
+        }
+        Tick::from_ticks(self.timer.get_capture_value(self.channel))
+    }
+}
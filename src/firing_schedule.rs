@@ -0,0 +1,215 @@
+use embassy_time::Duration;
+
+use crate::trigger_wheel::DecodeMode;
+
+#[cfg(feature = "defmt")]
+use defmt::Format;
+
+/// Largest number of cylinders a schedule can describe.
+pub const MAX_CYLINDERS: u8 = 12;
+
+/// A spark event of the firing schedule.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub struct SparkEvent {
+    /// Crank angle of the event within the decoding cycle, in degrees.
+    pub angle: f64,
+    /// Position of the cylinder in the firing order.
+    pub cylinder: u8,
+    /// Cylinder fired together with `cylinder` in wasted-spark mode.
+    pub companion: Option<u8>,
+}
+
+/// Evenly spaced firing of a four-stroke engine.
+///
+/// Cylinders are identified by their position in the firing order, the first
+/// one reaching TDC at 0° of the 720° cycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub struct FiringSchedule {
+    cylinders: u8,
+}
+
+impl FiringSchedule {
+    pub const fn new(cylinders: u8) -> Self {
+        assert!(cylinders > 0, "An engine needs at least one cylinder");
+        assert!(cylinders <= MAX_CYLINDERS, "Too many cylinders");
+
+        Self { cylinders }
+    }
+
+    pub fn cylinders(&self) -> u8 {
+        self.cylinders
+    }
+
+    /// Crank angle between two consecutive cylinders of the firing order.
+    pub fn spacing_degrees(&self) -> f64 {
+        720.0 / self.cylinders as f64
+    }
+
+    /// Engine speed above which `dwell` and the `spark` burning after it no
+    /// longer fit between two sparks of the same coil, with the cylinders
+    /// shared evenly among `coils` coils.
+    ///
+    /// On a four cylinder engine a distributor has one coil, wasted spark two
+    /// and coil-on-plug four.
+    pub fn max_supportable_rpm(&self, dwell: Duration, spark: Duration, coils: u8) -> f64 {
+        let window_degrees = self.spacing_degrees() * coils as f64;
+        let busy_seconds = (dwell + spark).as_micros() as f64 / 1_000_000.0;
+
+        // One RPM is 6° per second.
+        window_degrees / (6.0 * busy_seconds)
+    }
+
+    /// TDC angle of `cylinder` within the decoding cycle of `mode`, counted
+    /// from the TDC of the first cylinder. `TriggerWheel::tdc_degrees` gives
+    /// the angle on the wheel.
+    pub fn tdc_degrees(&self, cylinder: u8, mode: DecodeMode) -> f64 {
+        let angle = cylinder as f64 * self.spacing_degrees();
+        match mode {
+            DecodeMode::CrankOnly => angle % 360.0,
+            DecodeMode::Sequential => angle,
+        }
+    }
+
+    /// Cylinder sharing the TDC of `cylinder` one revolution later, if any.
+    pub fn companion(&self, cylinder: u8) -> Option<u8> {
+        match self.cylinders % 2 {
+            0 => Some((cylinder + self.cylinders / 2) % self.cylinders),
+            _ => None,
+        }
+    }
+
+    /// Spark events of one decoding cycle in `mode`, in firing order.
+    ///
+    /// Without cam phase the crank only covers 360°, so companion cylinders are
+    /// fired together and one of the two sparks is wasted on exhaust.
+    pub fn events(&self, mode: DecodeMode) -> impl Iterator<Item = SparkEvent> + '_ {
+        let (count, paired) = match (mode, self.companion(0)) {
+            (DecodeMode::CrankOnly, Some(_)) => (self.cylinders / 2, true),
+            _ => (self.cylinders, false),
+        };
+
+        (0..count).map(move |cylinder| SparkEvent {
+            angle: self.tdc_degrees(cylinder, mode),
+            cylinder,
+            companion: match paired {
+                true => self.companion(cylinder),
+                false => None,
+            },
+        })
+    }
+}
+
+impl Default for FiringSchedule {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn dwell_limit_of_a_four_cylinder() {
+        let schedule = FiringSchedule::new(4);
+
+        // A 3 ms dwell needs 180° at 10000 RPM on a distributor.
+        assert_close(
+            schedule.max_supportable_rpm(Duration::from_millis(3), Duration::MIN, 1),
+            10000.0,
+        );
+        assert_close(
+            schedule.max_supportable_rpm(Duration::from_millis(3), Duration::MIN, 2),
+            20000.0,
+        );
+        assert_close(
+            schedule.max_supportable_rpm(Duration::from_millis(5), Duration::MIN, 1),
+            6000.0,
+        );
+        assert_close(
+            schedule.max_supportable_rpm(Duration::from_millis(5), Duration::MIN, 4),
+            24000.0,
+        );
+    }
+
+    #[test]
+    fn dwell_limit_scales_with_the_event_count() {
+        let dwell = Duration::from_millis(4);
+
+        // Twice the events on a single coil, half the window.
+        let four = FiringSchedule::new(4).max_supportable_rpm(dwell, Duration::MIN, 1);
+        let eight = FiringSchedule::new(8).max_supportable_rpm(dwell, Duration::MIN, 1);
+        assert_close(four, 7500.0);
+        assert_close(eight, four / 2.0);
+    }
+
+    fn event(angle: f64, cylinder: u8, companion: Option<u8>) -> SparkEvent {
+        SparkEvent {
+            angle,
+            cylinder,
+            companion,
+        }
+    }
+
+    fn events(schedule: &FiringSchedule, mode: DecodeMode) -> heapless::Vec<SparkEvent, 12> {
+        schedule.events(mode).collect()
+    }
+
+    #[test]
+    fn companions_share_a_tdc_a_revolution_apart() {
+        let four = FiringSchedule::new(4);
+        assert_eq!(four.companion(0), Some(2));
+        assert_eq!(four.companion(1), Some(3));
+        assert_eq!(four.companion(2), Some(0));
+        assert_eq!(FiringSchedule::new(3).companion(0), None);
+    }
+
+    #[test]
+    fn crank_only_events_fire_companions_together() {
+        let four = FiringSchedule::new(4);
+        assert_eq!(
+            events(&four, DecodeMode::CrankOnly),
+            [event(0.0, 0, Some(2)), event(180.0, 1, Some(3))]
+        );
+
+        // Odd cylinder counts have no companion to waste a spark on.
+        assert_eq!(
+            events(&FiringSchedule::new(3), DecodeMode::CrankOnly),
+            [
+                event(0.0, 0, None),
+                event(240.0, 1, None),
+                event(120.0, 2, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn sequential_events_cover_the_whole_cycle() {
+        let six = FiringSchedule::new(6);
+        let expected: heapless::Vec<_, 12> = (0..6)
+            .map(|cylinder| event(cylinder as f64 * 120.0, cylinder, None))
+            .collect();
+        assert_eq!(events(&six, DecodeMode::Sequential), expected);
+    }
+
+    #[test]
+    fn the_spark_duration_shortens_the_dwell_window() {
+        let schedule = FiringSchedule::new(4);
+
+        // 3 ms of dwell and 1 ms of spark need 180° at 7500 RPM.
+        let dwell = Duration::from_millis(3);
+        let spark = Duration::from_millis(1);
+        assert_close(schedule.max_supportable_rpm(dwell, spark, 1), 7500.0);
+        assert_close(
+            schedule.max_supportable_rpm(dwell + spark, Duration::MIN, 1),
+            7500.0,
+        );
+        assert_close(schedule.max_supportable_rpm(dwell, spark, 2), 15000.0);
+    }
+}
@@ -0,0 +1,81 @@
+/// Deceleration fuel cut-off.
+///
+/// Fuel is cut when the throttle closes while the engine decelerates above
+/// the engage speed, and resumes as soon as the throttle opens or the speed
+/// falls to the resume speed. The gap between both speeds keeps the cut from
+/// chattering around a single threshold.
+pub struct DecelFuelCut {
+    engage_rpm: f64,
+    resume_rpm: f64,
+    active: bool,
+}
+
+impl DecelFuelCut {
+    pub const fn new(engage_rpm: f64, resume_rpm: f64) -> Self {
+        assert!(
+            engage_rpm > resume_rpm,
+            "Fuel cut must engage above the resume speed"
+        );
+
+        Self {
+            engage_rpm,
+            resume_rpm,
+            active: false,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Updates the decision from the engine speed, its rate of change in RPM
+    /// per second, and the throttle switch. Returns whether fuel is cut.
+    pub fn update(&mut self, rpm: f64, rpm_rate: f64, throttle_closed: bool) -> bool {
+        self.active = match self.active {
+            true => throttle_closed && rpm > self.resume_rpm,
+            false => throttle_closed && rpm > self.engage_rpm && rpm_rate < 0.0,
+        };
+        self.active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENGAGE_RPM: f64 = 1800.0;
+    const RESUME_RPM: f64 = 1200.0;
+
+    #[test]
+    fn engages_decelerating_on_a_closed_throttle() {
+        let mut cut = DecelFuelCut::new(ENGAGE_RPM, RESUME_RPM);
+        assert!(!cut.update(3000.0, -500.0, false));
+        assert!(!cut.update(3000.0, 200.0, true));
+        assert!(!cut.update(1500.0, -500.0, true));
+        assert!(cut.update(3000.0, -500.0, true));
+        assert!(cut.is_active());
+    }
+
+    #[test]
+    fn holds_down_to_the_resume_speed() {
+        let mut cut = DecelFuelCut::new(ENGAGE_RPM, RESUME_RPM);
+        assert!(cut.update(2500.0, -500.0, true));
+
+        // Between both speeds and even while the speed steadies, no chatter.
+        for rpm in [1800.0, 1500.0, 1700.0, 1201.0] {
+            assert!(cut.update(rpm, 0.0, true), "{rpm}");
+        }
+        assert!(!cut.update(RESUME_RPM, -500.0, true));
+
+        // Back above the resume speed is not enough to engage again.
+        assert!(!cut.update(1500.0, -500.0, true));
+    }
+
+    #[test]
+    fn an_opening_throttle_resumes_at_once() {
+        let mut cut = DecelFuelCut::new(ENGAGE_RPM, RESUME_RPM);
+        assert!(cut.update(2500.0, -500.0, true));
+        assert!(!cut.update(2400.0, -500.0, false));
+        assert!(!cut.is_active());
+    }
+}
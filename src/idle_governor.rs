@@ -0,0 +1,138 @@
+use embassy_time::Duration;
+
+/// PI controller holding the engine at a target idle RPM.
+///
+/// The output is a correction in whatever unit the gains are tuned for, such as
+/// idle valve duty or degrees of advance. Feed it a filtered RPM, the raw tooth
+/// to tooth RPM is noisy enough to keep the integrator busy for nothing.
+pub struct IdleGovernor {
+    target_rpm: f64,
+    kp: f32,
+    ki: f32,
+    min_output: f32,
+    max_output: f32,
+    // Integral term, already scaled by `ki`.
+    integral: f32,
+}
+
+impl IdleGovernor {
+    pub const fn new(target_rpm: f64, kp: f32, ki: f32) -> Self {
+        Self {
+            target_rpm,
+            kp,
+            ki,
+            min_output: f32::MIN,
+            max_output: f32::MAX,
+            integral: 0.0,
+        }
+    }
+
+    pub const fn with_output_limits(mut self, min_output: f32, max_output: f32) -> Self {
+        assert!(min_output <= max_output, "Output limits are inverted");
+
+        self.min_output = min_output;
+        self.max_output = max_output;
+        self
+    }
+
+    pub fn target_rpm(&self) -> f64 {
+        self.target_rpm
+    }
+
+    pub fn set_target_rpm(&mut self, target_rpm: f64) {
+        self.target_rpm = target_rpm;
+    }
+
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+    }
+
+    pub fn update(&mut self, measured_rpm: f64, dt: Duration) -> f32 {
+        let error = (self.target_rpm - measured_rpm) as f32;
+        let dt = dt.as_micros() as f32 / 1_000_000.0;
+
+        let proportional = self.kp * error;
+        let integral = self.integral + self.ki * error * dt;
+
+        let unclamped = proportional + integral;
+        let output = unclamped.clamp(self.min_output, self.max_output);
+
+        // Anti-windup: stop integrating while saturated, unless the error is
+        // pulling the output back inside the limits.
+        let saturated_high = unclamped > self.max_output && error > 0.0;
+        let saturated_low = unclamped < self.min_output && error < 0.0;
+        if !saturated_high && !saturated_low {
+            self.integral = integral;
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Engine idling at 700 RPM without correction, 50 RPM more per unit of
+    // correction, settling with a time constant of 200 ms.
+    fn settle(governor: &mut IdleGovernor, rpm: &mut f64, seconds: u32) -> f32 {
+        let dt = Duration::from_millis(10);
+        let mut output = 0.0;
+        for _ in 0..seconds * 100 {
+            output = governor.update(*rpm, dt);
+            let target = 700.0 + 50.0 * output as f64;
+            *rpm += (target - *rpm) * 0.01 / 0.2;
+        }
+        output
+    }
+
+    #[test]
+    fn reaches_the_target_without_steady_state_error() {
+        let mut governor = IdleGovernor::new(800.0, 0.01, 0.05);
+        let mut rpm = 700.0;
+        let output = settle(&mut governor, &mut rpm, 30);
+
+        assert!((rpm - 800.0).abs() < 0.5, "rpm {}", rpm);
+        assert!((output - 2.0).abs() < 0.01, "output {}", output);
+    }
+
+    #[test]
+    fn follows_a_new_target() {
+        let mut governor = IdleGovernor::new(800.0, 0.01, 0.05);
+        let mut rpm = 700.0;
+        settle(&mut governor, &mut rpm, 30);
+
+        governor.set_target_rpm(900.0);
+        settle(&mut governor, &mut rpm, 30);
+        assert!((rpm - 900.0).abs() < 0.5, "rpm {}", rpm);
+    }
+
+    #[test]
+    fn output_is_clamped() {
+        let mut governor = IdleGovernor::new(800.0, 1.0, 1.0).with_output_limits(-1.0, 1.0);
+        assert_eq!(governor.update(0.0, Duration::from_millis(10)), 1.0);
+        assert_eq!(governor.update(2000.0, Duration::from_millis(10)), -1.0);
+    }
+
+    #[test]
+    fn integrator_does_not_wind_up_while_saturated() {
+        let mut governor = IdleGovernor::new(800.0, 0.0, 1.0).with_output_limits(0.0, 1.0);
+        // Far below target for a long time, the output stays at the limit.
+        for _ in 0..1000 {
+            governor.update(600.0, Duration::from_millis(10));
+        }
+
+        // Once above target the output leaves the limit right away instead
+        // of unwinding a huge integral first.
+        let output = governor.update(900.0, Duration::from_millis(10));
+        assert!(output < 1.0, "output {}", output);
+    }
+
+    #[test]
+    fn reset_clears_the_integral() {
+        let mut governor = IdleGovernor::new(800.0, 0.0, 1.0);
+        governor.update(700.0, Duration::from_millis(100));
+        governor.reset();
+        assert_eq!(governor.update(800.0, Duration::from_millis(100)), 0.0);
+    }
+}
@@ -1,4 +1,22 @@
 #![no_std]
 
+pub mod advance_map;
+#[cfg(all(feature = "bench", target_os = "none"))]
+pub mod bench;
+#[cfg(target_os = "none")]
+pub mod capture;
+pub mod firing_schedule;
+pub mod fuel_cut;
+pub mod idle_governor;
 pub mod log;
+mod misfire;
+#[cfg(any(test, feature = "replay"))]
+pub mod replay;
+#[cfg(all(feature = "semihosting", target_os = "none"))]
+pub mod semihosting;
+pub mod tick;
+pub mod tick_generator;
+pub mod time;
+mod tooth_defect;
+pub mod trigger_pattern;
 pub mod trigger_wheel;
@@ -6,20 +6,34 @@ use panic_halt as _;
 #[cfg(feature = "defmt")]
 use {defmt_rtt as _, panic_probe as _};
 
+use crankshaft::capture::Capture;
 use crankshaft::trigger_wheel::TriggerWheel;
 use crankshaft::{debug, info};
 use embassy_executor::Spawner;
+use embassy_stm32::gpio::{Level, Output, Speed};
+use embassy_stm32::peripherals;
 use embassy_stm32::time::{hz, mhz, Hertz};
-use embassy_stm32::timer::{self, Channel};
-use embassy_stm32::{bind_interrupts, peripherals, Config};
-use embassy_stm32::{
-    gpio::{Level, Output, Pull, Speed},
-    timer::{
-        input_capture::{CapturePin, InputCapture},
-        low_level::CountingMode,
-    },
+use embassy_stm32::Config;
+use embassy_time::Timer;
+#[cfg(feature = "bench")]
+use {
+    crankshaft::bench,
+    crankshaft::tick_generator::TickGenerator,
+    crankshaft::trigger_pattern::{tooth_period, TriggerPattern},
 };
-use embassy_time::{Instant, Timer};
+#[cfg(not(any(feature = "replay", feature = "bench")))]
+use {
+    crankshaft::error,
+    embassy_stm32::bind_interrupts,
+    embassy_stm32::gpio::Pull,
+    embassy_stm32::timer::{self, input_capture::CapturePin},
+    embassy_time::Instant,
+};
+#[cfg(all(
+    feature = "semihosting",
+    not(any(feature = "replay", feature = "bench"))
+))]
+use {crankshaft::semihosting::Exporter, crankshaft::trigger_pattern::TriggerPattern};
 
 // Timer frequency for input capture.
 //
@@ -29,12 +43,17 @@ use embassy_time::{Instant, Timer};
 //   - 2.5 ms between teeth = 2500 timer ticks per tooth
 // - Average RPM (3000 RPM):
 //   - 50 revolutions per second = 1500 teeth per second
-//   - 0.67 ms between teeth = 670 timer ticks per tooth
+//   - 0.67 ms between teeth = 667 timer ticks per tooth
 // - Maximum RPM (6000 RPM):
 //   - 100 revolutions per second = 3000 teeth per second
-//   - 0.33 ms between teeth = 330 timer ticks per tooth
+//   - 0.33 ms between teeth = 333 timer ticks per tooth
+//
+// See `crankshaft::trigger_pattern::tooth_period` to derive these periods.
+// The decoder must be done with a tooth before the next one, which the `bench`
+// feature checks on the target.
 const TIMER_FREQ: Hertz = mhz(1);
 
+#[cfg(not(any(feature = "replay", feature = "bench")))]
 bind_interrupts!(struct Irqs {
     TIM2 => timer::CaptureCompareInterruptHandler<peripherals::TIM2>;
 });
@@ -161,41 +180,101 @@ async fn main(spawner: Spawner) {
     let led = Output::new(p.PA5, Level::Low, Speed::Low);
     spawner.spawn(blink_led(led)).unwrap();
 
-    let (mut ic, ch) = {
-        let ch2 = CapturePin::new_ch2(p.PB3, Pull::None);
-        let ic = InputCapture::new(
-            p.TIM2,
-            None,
-            Some(ch2),
-            None,
-            None,
-            Irqs,
-            TIMER_FREQ,
-            CountingMode::EdgeAlignedUp,
-        );
-        (ic, Channel::Ch2)
-    };
-
-    let mut trigger_wheel: TriggerWheel<120> = TriggerWheel::new();
+    // Replayed captures come from TIM2 too.
+    let mut trigger_wheel: TriggerWheel<120> = TriggerWheel::new()
+        .with_timer_freq(TIMER_FREQ)
+        .with_timer_bits(Capture::<peripherals::TIM2>::TIMER_BITS);
+
+    #[cfg(not(any(feature = "replay", feature = "bench")))]
+    listen(p.TIM2, p.PB3, &mut trigger_wheel).await;
+
+    #[cfg(feature = "replay")]
+    replay(&mut trigger_wheel);
+
+    #[cfg(feature = "bench")]
+    bench(&mut trigger_wheel);
+}
+
+#[cfg(not(any(feature = "replay", feature = "bench")))]
+async fn listen<const N: usize>(
+    tim2: peripherals::TIM2,
+    pin: peripherals::PB3,
+    trigger_wheel: &mut TriggerWheel<N>,
+) -> ! {
+    let ch2 = CapturePin::new_ch2(pin, Pull::None);
+    let mut capture = Capture::new(tim2, ch2, Irqs, TIMER_FREQ);
+    #[cfg(feature = "semihosting")]
+    let mut exporter = Exporter::new(trigger_wheel.pattern().teeth_present());
 
     loop {
-        ic.wait_for_rising_edge(ch).await;
+        let tick = capture.next_tick().await;
 
         let mcu_instant = Instant::now();
         let mcu_ticks = mcu_instant.as_ticks();
         let mcu_millis = mcu_instant.as_millis();
 
-        let tim2_ticks = ic.get_capture_value(ch);
+        let tim2_ticks = tick.as_ticks();
         let tim2_millis = tim2_ticks as u64 / 1000;
-        let tim2_instant = Instant::from_ticks(tim2_ticks as u64);
 
-        let interval = trigger_wheel.add_tick(&tim2_instant);
+        let interval = trigger_wheel.add_tick(tick);
 
-        if let Some(duration) = interval {
-            info!(
+        #[cfg(feature = "semihosting")]
+        exporter.export(trigger_wheel);
+
+        match interval {
+            Ok(Some(duration)) => info!(
                 " MCU's clock ticks: {} ~ {} ms, TIM2' clock ticks: {} ~ {} ms, + {}",
                 mcu_ticks, mcu_millis, tim2_ticks, tim2_millis, duration
-            );
+            ),
+            Ok(None) => {}
+            Err(e) => error!("Tick {} rejected: {}", tim2_ticks, e),
         }
     }
 }
+
+// Feeds a canned capture through the decoder instead of the sensor. The
+// capture is an array literal of raw TIM2 values, included at build time from
+// the file named by the `CRANKSHAFT_REPLAY` environment variable, or else
+// from `fixtures/sixty_minus_two.in`, see `build.rs`.
+#[cfg(feature = "replay")]
+fn replay<const N: usize>(trigger_wheel: &mut TriggerWheel<N>) {
+    static CAPTURE: &[u32] = &include!(env!("CRANKSHAFT_REPLAY_CAPTURE"));
+
+    for event in crankshaft::replay::replay(trigger_wheel, CAPTURE) {
+        info!("{}", event);
+    }
+}
+
+// Measures the cost of the decoder on the ticks of the wheel turning at the
+// redline, from the first tooth on so that acquiring the sync is measured too,
+// and panics when a tooth costs more than the time to the next one.
+#[cfg(feature = "bench")]
+fn bench<const N: usize>(trigger_wheel: &mut TriggerWheel<N>) {
+    const REDLINE_RPM: f64 = 6000.0;
+    const REVOLUTIONS: usize = 100;
+    // See the clock configuration in `main`.
+    const CORE_FREQ: Hertz = mhz(48);
+
+    let mut syst = cortex_m::Peripherals::take().unwrap().SYST;
+
+    let pattern = *trigger_wheel.pattern();
+    let teeth = pattern.teeth_per_rev();
+    let ticks = TickGenerator::new(pattern, REDLINE_RPM)
+        .with_timer_freq(TIMER_FREQ)
+        .take(REVOLUTIONS * teeth as usize);
+
+    let cost = bench::measure(&mut syst, trigger_wheel, ticks);
+    let budget = tooth_period(REDLINE_RPM, teeth);
+
+    info!(
+        "add_tick over {} teeth: worst {} cycles, mean {} cycles, budget {} cycles",
+        cost.calls,
+        cost.worst_cycles,
+        cost.mean_cycles,
+        bench::budget_cycles(budget, CORE_FREQ)
+    );
+    assert!(
+        cost.fits(budget, CORE_FREQ),
+        "add_tick exceeds the tooth period at the redline"
+    );
+}
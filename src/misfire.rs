@@ -0,0 +1,67 @@
+use crate::firing_schedule::{FiringSchedule, MAX_CYLINDERS};
+
+// Time the crank spends in the power stroke segment of each cylinder over a
+// 720° cycle, the segment of a cylinder starting at its TDC.
+pub(crate) struct Segments {
+    // Accumulated over the current cycle, in microseconds.
+    current: [u64; MAX_CYLINDERS as usize],
+    // Whether the current cycle was followed from its start.
+    started: bool,
+    // Average angular velocity of each segment over the last complete cycle.
+    velocities: Option<[f32; MAX_CYLINDERS as usize]>,
+}
+
+impl Segments {
+    pub(crate) const fn new() -> Self {
+        Self {
+            current: [0; MAX_CYLINDERS as usize],
+            started: false,
+            velocities: None,
+        }
+    }
+
+    // Accounts for an interval of `micros` starting at `start_angle` within
+    // the cycle.
+    pub(crate) fn record(&mut self, schedule: &FiringSchedule, start_angle: f64, micros: u64) {
+        let segment = (start_angle / schedule.spacing_degrees()) as usize;
+        if let Some(time) = self.current.get_mut(segment) {
+            *time += micros;
+        }
+    }
+
+    // Closes the current cycle at 0° and starts the next one.
+    pub(crate) fn start_cycle(&mut self, schedule: &FiringSchedule) {
+        let cylinders = schedule.cylinders() as usize;
+        let spacing = schedule.spacing_degrees();
+
+        if self.started && self.current[..cylinders].iter().all(|&time| time > 0) {
+            let mut velocities = [0.0; MAX_CYLINDERS as usize];
+            for (velocity, &time) in velocities.iter_mut().zip(&self.current[..cylinders]) {
+                *velocity = (spacing * 1_000_000.0 / time as f64) as f32;
+            }
+            self.velocities = Some(velocities);
+        }
+
+        self.current = [0; MAX_CYLINDERS as usize];
+        self.started = true;
+    }
+
+    // Forgets everything once the position in the cycle is no longer known.
+    pub(crate) fn invalidate(&mut self) {
+        *self = Self::new();
+    }
+
+    // Slowest cylinder of the last cycle along with its deviation from the
+    // average velocity, as a fraction of the average.
+    pub(crate) fn weakest(&self, schedule: &FiringSchedule) -> Option<(u8, f32)> {
+        let velocities = &self.velocities.as_ref()?[..schedule.cylinders() as usize];
+
+        let mean = velocities.iter().sum::<f32>() / velocities.len() as f32;
+        let (cylinder, slowest) = velocities
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+        Some((cylinder as u8, (mean - slowest) / mean))
+    }
+}
@@ -0,0 +1,95 @@
+use core::slice::Iter;
+
+use embassy_time::Duration;
+
+use crate::tick::{Tick, TickError};
+use crate::trigger_pattern::TriggerPattern;
+use crate::trigger_wheel::{SyncState, TriggerWheel};
+
+#[cfg(feature = "defmt")]
+use defmt::Format;
+
+/// Decoder state right after a replayed tick.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub struct ReplayEvent {
+    pub tick: Tick,
+    pub interval: Result<Option<Duration>, TickError>,
+    pub gap: bool,
+    pub sync: SyncState,
+    pub crank_angle: Option<f64>,
+    pub rpm: Option<f64>,
+}
+
+/// Feeds captured timer values through a trigger wheel, one per iteration.
+pub struct Replay<'w, const N: usize, P> {
+    wheel: &'w mut TriggerWheel<N, P>,
+    ticks: Iter<'static, u32>,
+}
+
+/// Replays raw input capture values, as read from the timer, through `wheel`.
+///
+/// The ticks go through `add_tick` exactly like live captures, so a recorded
+/// dataset reproduces the decoding of the installation it was captured on.
+pub fn replay<'w, const N: usize, P: TriggerPattern>(
+    wheel: &'w mut TriggerWheel<N, P>,
+    ticks: &'static [u32],
+) -> Replay<'w, N, P> {
+    Replay {
+        wheel,
+        ticks: ticks.iter(),
+    }
+}
+
+impl<const N: usize, P: TriggerPattern> Iterator for Replay<'_, N, P> {
+    type Item = ReplayEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tick = Tick::from_ticks(*self.ticks.next()?);
+        let interval = self.wheel.add_tick(tick);
+
+        Some(ReplayEvent {
+            tick,
+            interval,
+            gap: self.wheel.last_was_gap(),
+            sync: self.wheel.sync_state(),
+            crank_angle: self.wheel.crank_angle_degrees(),
+            rpm: self.wheel.rpm(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::mhz;
+
+    static CAPTURE: &[u32] = &include!("../fixtures/sixty_minus_two.in");
+
+    #[test]
+    fn the_fixture_syncs_on_its_gap() {
+        let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_timer_freq(mhz(1));
+        let mut events = replay(&mut wheel, CAPTURE);
+
+        // Teeth 40 to 57, the interval into 40 is the first one measured.
+        for event in events.by_ref().take(18) {
+            assert_eq!(event.sync, SyncState::Searching);
+            assert!(!event.gap);
+            assert_eq!(event.crank_angle, None);
+        }
+
+        let synced = events.next().unwrap();
+        assert_eq!(synced.sync, SyncState::Synced);
+        assert!(synced.gap);
+        assert_eq!(synced.crank_angle, Some(0.0));
+
+        // Stays synced over the two following gaps, 6° per tooth.
+        for (tooth, event) in events.enumerate() {
+            let position = (tooth + 1) % 58;
+            assert_eq!(event.sync, SyncState::Synced);
+            assert_eq!(event.gap, position == 0);
+            assert_eq!(event.crank_angle, Some(position as f64 * 6.0));
+            assert!((event.rpm.unwrap() - 3000.0).abs() < 10.0);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use core::fmt::{self, Display, Formatter};
+
+use cortex_m_semihosting::hprintln;
+
+use crate::trigger_pattern::TriggerPattern;
+use crate::trigger_wheel::{SyncState, TriggerWheel};
+
+/// Prints the decoder state to the host once every so many teeth, synced or
+/// not.
+///
+/// Every semihosting call halts the core until the debugger has serviced
+/// it, so this is meant to be called after each `add_tick` on the bench only.
+/// Records are comma separated lines, empty fields standing for unknown values:
+///
+/// ```text
+/// crankshaft,<ticks count>,<sync>,<phase>,<crank angle>,<rpm>,<filtered rpm>,<residual rpm>,<dropouts>,<implausible ticks>
+/// ```
+///
+/// The sync is `searching` or `synced`.
+pub struct Exporter {
+    every: u16,
+    // Teeth since the last record.
+    teeth: u16,
+}
+
+impl Exporter {
+    /// Prints a record on the first tooth and then every `every` teeth, the
+    /// teeth present on the wheel giving one record per revolution. An
+    /// `every` of `0` is taken as `1`.
+    pub const fn new(every: u16) -> Self {
+        Self {
+            every: if every == 0 { 1 } else { every },
+            teeth: 0,
+        }
+    }
+
+    /// Counts a tooth, printing a record when one is due.
+    pub fn export<const N: usize, P: TriggerPattern>(&mut self, wheel: &TriggerWheel<N, P>) {
+        let due = self.teeth == 0;
+        self.teeth = (self.teeth + 1) % self.every;
+        if !due {
+            return;
+        }
+
+        let rpm = wheel.rpm();
+        let rpm_filtered = wheel.rpm_filtered();
+        let residual = rpm.zip(rpm_filtered).map(|(raw, filtered)| raw - filtered);
+        let sync = match wheel.sync_state() {
+            SyncState::Searching => "searching",
+            SyncState::Synced => "synced",
+        };
+
+        hprintln!(
+            "crankshaft,{},{},{},{},{},{},{},{},{}",
+            wheel.ticks_count(),
+            sync,
+            Field(wheel.phase()),
+            Field(wheel.crank_angle_degrees()),
+            Field(rpm),
+            Field(rpm_filtered),
+            Field(residual),
+            wheel.dropouts(),
+            wheel.implausible_ticks(),
+        );
+    }
+}
+
+struct Field<T>(Option<T>);
+
+impl<T: Display> Display for Field<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(ref value) => write!(f, "{:.1}", value),
+            None => Ok(()),
+        }
+    }
+}
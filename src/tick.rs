@@ -0,0 +1,44 @@
+use embassy_time::Instant;
+
+use crate::time::Hertz;
+
+#[cfg(feature = "defmt")]
+use defmt::Format;
+
+/// Raw counter value of the input capture timer at a tooth edge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub struct Tick(u32);
+
+/// Reason for a tick to be rejected by the decoder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub enum TickError {
+    /// Same instant as the previous tick, most likely the same edge captured
+    /// twice rather than a zero length interval.
+    Duplicate,
+    /// Implies the crank sped up faster than the configured limit allows
+    /// since the previous tooth, most likely a spurious edge.
+    Implausible,
+    /// Before the previous tick, more than half the counter range behind
+    /// it for a captured one.
+    OutOfOrder,
+}
+
+impl Tick {
+    pub const fn from_ticks(ticks: u32) -> Self {
+        Self(ticks)
+    }
+
+    pub const fn as_ticks(&self) -> u32 {
+        self.0
+    }
+
+    /// Converts the counter value into an instant for a timer running at `freq`.
+    ///
+    /// The resolution is limited to the one of the embassy time base. Counter
+    /// overflows are not accounted for.
+    pub fn to_instant(self, freq: Hertz) -> Instant {
+        Instant::from_micros(self.0 as u64 * 1_000_000 / freq.0 as u64)
+    }
+}
@@ -0,0 +1,183 @@
+use embassy_time::Instant;
+// Float math for `core`, unused where std provides it.
+#[allow(unused_imports)]
+use nalgebra::ComplexField;
+
+use crate::tick::Tick;
+use crate::time::{mhz, Hertz};
+use crate::trigger_pattern::TriggerPattern;
+
+/// Produces the ticks a wheel of pattern `P` shows turning at a commanded
+/// speed, to drive the decoder without an engine, e.g. on a bench or in CI.
+///
+/// The first tooth is position `0` at time zero, right after the reference
+/// gap, and every tooth after it follows at the commanded speed, gaps lasting
+/// as many tooth periods as they span. The speed may be changed between teeth
+/// with `set_rpm`, or ramped continuously with `set_rpm_rate`. Ticks are
+/// counter values for a timer running at the configured frequency, to be fed
+/// to `add_tick`, while `next_instant` suits `add_tick_at`.
+pub struct TickGenerator<P> {
+    pattern: P,
+    timer_freq: Hertz,
+    rpm: f64,
+    // Rate of change of the speed, in RPM per second.
+    rpm_rate: f64,
+    // Position of the next tooth.
+    position: u16,
+    // Time of the next tooth, in seconds.
+    time: f64,
+}
+
+impl<P: TriggerPattern> TickGenerator<P> {
+    pub fn new(pattern: P, rpm: f64) -> Self {
+        Self {
+            pattern,
+            timer_freq: mhz(1),
+            rpm,
+            rpm_rate: 0.0,
+            position: 0,
+            time: 0.0,
+        }
+    }
+
+    /// Frequency of the timer the ticks are counted by, the one given to
+    /// `TriggerWheel::with_timer_freq`.
+    pub fn with_timer_freq(mut self, freq: Hertz) -> Self {
+        self.timer_freq = freq;
+        self
+    }
+
+    /// Speed at the next tooth.
+    pub fn rpm(&self) -> f64 {
+        self.rpm
+    }
+
+    pub fn set_rpm(&mut self, rpm: f64) {
+        self.rpm = rpm;
+    }
+
+    /// Changes the speed by `rpm_per_sec` every second from the next tooth on.
+    ///
+    /// The teeth are spaced for the speed changing continuously in between.
+    pub fn set_rpm_rate(&mut self, rpm_per_sec: f64) {
+        self.rpm_rate = rpm_per_sec;
+    }
+
+    /// Position of the next tooth on the wheel.
+    pub fn position(&self) -> u16 {
+        self.position
+    }
+
+    /// Counter value of the next tooth, `None` once the crank stopped.
+    pub fn next_tick(&mut self) -> Option<Tick> {
+        let seconds = self.advance()?;
+        let ticks = (seconds * self.timer_freq.0 as f64) as u64;
+        // The counter wraps like the hardware one.
+        Some(Tick::from_ticks(ticks as u32))
+    }
+
+    /// Instant of the next tooth, `None` once the crank stopped.
+    ///
+    /// Limited to the resolution of the embassy time base, and without the
+    /// wrapping of the counter.
+    pub fn next_instant(&mut self) -> Option<Instant> {
+        let seconds = self.advance()?;
+        Some(Instant::from_micros((seconds * 1_000_000.0) as u64))
+    }
+
+    // Returns the time of the next tooth and moves on to the one after it.
+    fn advance(&mut self) -> Option<f64> {
+        if self.rpm <= 0.0 {
+            return None;
+        }
+
+        let time = self.time;
+        let next = self.pattern.next_tooth(self.position);
+        let teeth = self.pattern.teeth_per_rev();
+        let positions = match (next + teeth - self.position) % teeth {
+            0 => teeth,
+            distance => distance,
+        };
+        let degrees = positions as f64 * self.pattern.degrees_per_tooth();
+
+        // Angular velocity and acceleration in degrees per second (squared).
+        let velocity = self.rpm * 6.0;
+        let acceleration = self.rpm_rate * 6.0;
+
+        // Solves degrees = v·t + a·t²/2 for the time to the next tooth.
+        let dt = match acceleration == 0.0 {
+            true => degrees / velocity,
+            false => {
+                let discriminant = velocity * velocity + 2.0 * acceleration * degrees;
+                if discriminant < 0.0 {
+                    // Stops before reaching the next tooth.
+                    self.rpm = 0.0;
+                    return Some(time);
+                }
+                (discriminant.sqrt() - velocity) / acceleration
+            }
+        };
+
+        self.time += dt;
+        self.rpm += self.rpm_rate * dt;
+        self.position = next;
+        Some(time)
+    }
+}
+
+impl<P: TriggerPattern> Iterator for TickGenerator<P> {
+    type Item = Tick;
+
+    fn next(&mut self) -> Option<Tick> {
+        self.next_tick()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trigger_pattern::SixtyMinusTwo;
+
+    #[test]
+    fn teeth_and_gap_are_spaced_for_the_speed() {
+        // 6° in 333.3 µs at 3000 RPM, the gap lasting 3 tooth periods.
+        let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+        let ticks: heapless::Vec<u32, 64> =
+            generator.by_ref().take(60).map(|t| t.as_ticks()).collect();
+        assert_eq!(&ticks[..4], &[0, 333, 666, 1000]);
+        assert_eq!(ticks[57], 19000);
+        assert_eq!(ticks[58], 20000);
+        assert_eq!(generator.position(), 2);
+
+        // Timer ticks follow the timer frequency.
+        let mut fast =
+            TickGenerator::new(SixtyMinusTwo::default(), 3000.0).with_timer_freq(mhz(12));
+        fast.next_tick();
+        assert_eq!(fast.next_tick(), Some(Tick::from_ticks(4000)));
+    }
+
+    #[test]
+    fn a_ramp_changes_the_speed_between_teeth() {
+        let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 1000.0);
+        generator.set_rpm_rate(1000.0);
+        let first = generator.next_tick().unwrap().as_ticks();
+        let second = generator.next_tick().unwrap().as_ticks();
+        for _ in 0..58 {
+            generator.next_tick();
+        }
+        let late = generator.next_tick().unwrap().as_ticks();
+        let later = generator.next_tick().unwrap().as_ticks();
+        assert!(generator.rpm() > 1000.0);
+        assert!(later - late < second - first);
+    }
+
+    #[test]
+    fn a_deceleration_stops_the_crank() {
+        let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 100.0);
+        generator.set_rpm_rate(-1000.0);
+        let teeth = generator.by_ref().take(1000).count();
+        assert!(teeth < 1000, "{teeth}");
+        assert_eq!(generator.next_tick(), None);
+        assert_eq!(generator.next_instant(), None);
+    }
+}
@@ -0,0 +1,24 @@
+//! Frequencies of the timers, as in `embassy_stm32::time`.
+//!
+//! The HAL only builds for the target, host builds running the tests get a
+//! stand-in of the same shape.
+
+#[cfg(target_os = "none")]
+pub use embassy_stm32::time::{hz, mhz, Hertz};
+
+#[cfg(not(target_os = "none"))]
+pub use host::{hz, mhz, Hertz};
+
+#[cfg(not(target_os = "none"))]
+mod host {
+    #[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Copy, Debug)]
+    pub struct Hertz(pub u32);
+
+    pub const fn hz(hertz: u32) -> Hertz {
+        Hertz(hertz)
+    }
+
+    pub const fn mhz(megahertz: u32) -> Hertz {
+        Hertz(megahertz * 1_000_000)
+    }
+}
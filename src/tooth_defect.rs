@@ -0,0 +1,80 @@
+use crate::trigger_pattern::TriggerPattern;
+
+// Revolutions averaged by the deviation of each tooth, and needed before a
+// tooth is suspected at all.
+const AVERAGED_REVOLUTIONS: u8 = 8;
+
+// Displacement of a tooth edge from its place from which the tooth is
+// suspect, as a fraction of a tooth period.
+const SUSPECT_DISPLACEMENT: f32 = 0.05;
+
+// Deviation of the interval ending on each tooth position from the tooth
+// period, averaged over revolutions. Random jitter averages out, a damaged
+// tooth shows at the same position every revolution.
+//
+// Sized by the length `N` of the tick history, which `TriggerWheel` checks
+// exceeds the tooth positions of its pattern, so there is a mean for each.
+pub(crate) struct ToothDeviations<const N: usize> {
+    // As a fraction of the tooth period, one per tooth position.
+    means: [f32; N],
+    // Complete revolutions averaged so far, saturating.
+    revolutions: u8,
+}
+
+impl<const N: usize> ToothDeviations<N> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            means: [0.0; N],
+            revolutions: 0,
+        }
+    }
+
+    // Accounts for the deviation of the interval ending on the tooth at
+    // `position`.
+    pub(crate) fn record(&mut self, position: u16, deviation: f32) {
+        // A plain average over the first revolutions, then an exponential one.
+        let weight = 1.0 / (self.revolutions.min(AVERAGED_REVOLUTIONS - 1) + 1) as f32;
+        let mean = &mut self.means[position as usize];
+        *mean += (deviation - *mean) * weight;
+    }
+
+    pub(crate) fn complete_revolution(&mut self) {
+        self.revolutions = self.revolutions.saturating_add(1);
+    }
+
+    // Forgets everything once the position on the wheel is no longer known.
+    pub(crate) fn invalidate(&mut self) {
+        *self = Self::new();
+    }
+
+    // Tooth whose edge sits the furthest from its place, if far enough.
+    //
+    // A misplaced edge shortens the interval ending on it and lengthens the
+    // next one by as much, so the displacement is half their difference. A
+    // smooth speed variation such as the one from combustion moves both
+    // intervals alike and cancels out.
+    pub(crate) fn suspect(&self, pattern: &impl TriggerPattern) -> Option<u16> {
+        if self.revolutions < AVERAGED_REVOLUTIONS {
+            return None;
+        }
+
+        let mut suspect: Option<(u16, f32)> = None;
+        for position in 0..pattern.teeth_per_rev() {
+            if pattern.is_missing(position) {
+                continue;
+            }
+
+            let next = pattern.next_tooth(position);
+            let displacement = (self.means[next as usize] - self.means[position as usize]) * 0.5;
+            let displacement = displacement.abs();
+
+            if displacement >= SUSPECT_DISPLACEMENT
+                && suspect.is_none_or(|(_, largest)| displacement > largest)
+            {
+                suspect = Some((position, displacement));
+            }
+        }
+
+        suspect.map(|(position, _)| position)
+    }
+}
@@ -0,0 +1,276 @@
+use embassy_time::{Duration, TICK_HZ};
+
+#[cfg(feature = "defmt")]
+use defmt::Format;
+
+/// A run of missing teeth on the wheel.
+///
+/// Tooth positions are numbered from `0` to `teeth_per_rev() - 1`, as if the
+/// wheel had no missing teeth. Position `0` is the first tooth after the
+/// reference gap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub struct Gap {
+    /// Position of the first missing tooth.
+    pub position: u16,
+    /// Number of missing teeth, one less than the tooth periods the gap
+    /// lasts, see [`TriggerPattern::gap_period_multiplier`].
+    pub width: u16,
+}
+
+/// Geometry of a trigger wheel.
+pub trait TriggerPattern {
+    /// Tooth positions per revolution when known at compile time, for patterns
+    /// whose geometry is part of their type.
+    const TEETH_PER_REV: Option<u16> = None;
+
+    /// Number of evenly spaced tooth positions per revolution, missing teeth included.
+    fn teeth_per_rev(&self) -> u16;
+
+    /// Gaps of the wheel ordered around it from the reference gap, the one
+    /// ending at position `0`.
+    fn gaps(&self) -> &[Gap];
+
+    /// Whether the position is resolved from the spacing of consecutive
+    /// teeth, for wheels with so few teeth that no interval is a normal tooth
+    /// period to judge the reference gap against.
+    ///
+    /// Every tooth must then follow a pair of spacings no other one follows.
+    /// The reference gap is the only one confirmed, as signalled by
+    /// `TriggerWheel::with_gap_signal` and counted by `ticks_since_gap`.
+    fn resolved_by_spacing(&self) -> bool {
+        false
+    }
+
+    /// Crank angle from position `0` to the TDC of the first cylinder of the
+    /// firing order, in degrees.
+    fn tdc_offset_degrees(&self) -> f64 {
+        0.0
+    }
+
+    /// Angle between two consecutive tooth positions, the angular resolution
+    /// of the wheel, in degrees.
+    ///
+    /// Positions are counted with the missing teeth, so this is 6° on a 60-2
+    /// wheel and not 360°/58, and a gap covers as many of these as it lasts
+    /// tooth periods. Counts that don't divide 360 are fine, the angle is then
+    /// fractional.
+    fn degrees_per_tooth(&self) -> f64 {
+        360.0 / self.teeth_per_rev() as f64
+    }
+
+    /// Length of the reference gap in tooth periods, from the last tooth
+    /// before it to the first one after it, `1.0` on wheels without a gap.
+    ///
+    /// This is the number of missing teeth plus one: the interval over the
+    /// gap of a 60-2 wheel lasts 3 tooth periods, not 2, and the one of a
+    /// 36-1 wheel lasts 2. It is what the gap interval is expected to measure
+    /// against a normal one.
+    fn gap_period_multiplier(&self) -> f64 {
+        self.gaps()
+            .first()
+            .map_or(1.0, |gap| (gap.width + 1) as f64)
+    }
+
+    /// Number of teeth physically present on the wheel.
+    fn teeth_present(&self) -> u16 {
+        let missing: u16 = self.gaps().iter().map(|gap| gap.width).sum();
+        self.teeth_per_rev() - missing
+    }
+
+    fn is_missing(&self, position: u16) -> bool {
+        self.gaps()
+            .iter()
+            .any(|gap| (gap.position..gap.position + gap.width).contains(&position))
+    }
+
+    /// Position of the first present tooth after `position`, wrapping around the wheel.
+    fn next_tooth(&self, position: u16) -> u16 {
+        let teeth = self.teeth_per_rev();
+        let mut next = (position + 1) % teeth;
+        while self.is_missing(next) && next != position {
+            next = (next + 1) % teeth;
+        }
+        next
+    }
+
+    /// Position of the first present tooth before `position`, wrapping around the wheel.
+    fn prev_tooth(&self, position: u16) -> u16 {
+        let teeth = self.teeth_per_rev();
+        let mut prev = (position + teeth - 1) % teeth;
+        while self.is_missing(prev) && prev != position {
+            prev = (prev + teeth - 1) % teeth;
+        }
+        prev
+    }
+}
+
+/// Time between two consecutive teeth of a wheel with `teeth` positions per
+/// revolution turning at `rpm`.
+///
+/// Meant for deriving thresholds such as the debounce from engine speeds. The
+/// period is rounded down to the resolution of the embassy time base, so a
+/// threshold derived from it stays under the real period.
+pub const fn tooth_period(rpm: f64, teeth: u16) -> Duration {
+    Duration::from_ticks((TICK_HZ as f64 * 60.0 / (rpm * teeth as f64)) as u64)
+}
+
+/// A wheel with `TEETH` evenly spaced positions and `MISSING` consecutive teeth
+/// removed right before position `0`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub struct MissingTooth<const TEETH: u16, const MISSING: u16>;
+
+impl<const TEETH: u16, const MISSING: u16> MissingTooth<TEETH, MISSING> {
+    const GAPS: [Gap; 1] = [Gap {
+        position: TEETH - MISSING,
+        width: MISSING,
+    }];
+}
+
+impl<const TEETH: u16, const MISSING: u16> TriggerPattern for MissingTooth<TEETH, MISSING> {
+    const TEETH_PER_REV: Option<u16> = Some(TEETH);
+
+    fn teeth_per_rev(&self) -> u16 {
+        TEETH
+    }
+
+    fn gaps(&self) -> &[Gap] {
+        &Self::GAPS
+    }
+}
+
+/// A wheel with `TEETH` evenly spaced teeth and no gap.
+///
+/// The crank signal alone can't tell one tooth from another, such wheels are
+/// synced by the cam pulse in sequential mode, the first tooth after it being
+/// position `0` of the cycle. A lost tooth goes unnoticed until the next cam
+/// pulse realigns the position.
+///
+/// In crank-only mode they never sync, leaving the speed measurement alone
+/// with its bounce and duplicate rejection, as needed for a [`ToneRing`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub struct EvenTeeth<const TEETH: u16>;
+
+impl<const TEETH: u16> TriggerPattern for EvenTeeth<TEETH> {
+    const TEETH_PER_REV: Option<u16> = Some(TEETH);
+
+    fn teeth_per_rev(&self) -> u16 {
+        TEETH
+    }
+
+    fn gaps(&self) -> &[Gap] {
+        &[]
+    }
+}
+
+/// Plain tone ring with `TEETH` teeth and no reference, such as the reluctor
+/// of a driveshaft speed sensor. Only the speed is known, not the angle.
+pub type ToneRing<const TEETH: u16> = EvenTeeth<TEETH>;
+
+pub type SixtyMinusTwo = MissingTooth<60, 2>;
+pub type ThirtySixMinusOne = MissingTooth<36, 1>;
+pub type TwentyFourMinusOne = MissingTooth<24, 1>;
+pub type TwelveMinusOne = MissingTooth<12, 1>;
+
+/// Three unevenly spaced crank pulses per revolution along with one cam pulse
+/// per cycle, in the style of GM 3X/1X engines.
+///
+/// The pulses sit at positions `0`, `2` and `6` of twelve 30° positions, so
+/// they are 60°, 120° and 180° apart and each one follows a pair of spacings
+/// of its own. The position is resolved from the ratio of the last two
+/// intervals, from the third pulse on. Sequential mode needs the cam pulse
+/// besides for the 720° cycle, as with any wheel. The TDC of the first
+/// cylinder is at the second pulse, 60° past position `0`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub struct GmThreeX;
+
+impl GmThreeX {
+    const GAPS: [Gap; 3] = [
+        Gap {
+            position: 7,
+            width: 5,
+        },
+        Gap {
+            position: 1,
+            width: 1,
+        },
+        Gap {
+            position: 3,
+            width: 3,
+        },
+    ];
+}
+
+impl TriggerPattern for GmThreeX {
+    const TEETH_PER_REV: Option<u16> = Some(12);
+
+    fn teeth_per_rev(&self) -> u16 {
+        12
+    }
+
+    fn gaps(&self) -> &[Gap] {
+        &Self::GAPS
+    }
+
+    fn resolved_by_spacing(&self) -> bool {
+        true
+    }
+
+    fn tdc_offset_degrees(&self) -> f64 {
+        60.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tick_generator::TickGenerator;
+
+    #[test]
+    fn tooth_period_of_the_documented_thirty_teeth_wheel() {
+        // Idle, average and maximum speeds from the timer comment in `main`,
+        // to within the 10 µs of the time base below.
+        for (rpm, micros) in [(800.0, 2500), (3000.0, 667), (6000.0, 333)] {
+            let period = tooth_period(rpm, 30).as_micros();
+            assert!(period <= micros && micros - period < 10, "{rpm}: {period}");
+        }
+        assert_eq!(tooth_period(800.0, 30), Duration::from_micros(2500));
+    }
+
+    #[test]
+    fn tooth_period_counts_the_missing_teeth() {
+        // 60-2 at 6000 RPM, 6° in 166.7 µs.
+        assert_eq!(tooth_period(6000.0, 60), Duration::from_micros(160));
+    }
+
+    #[test]
+    fn gap_period_multiplier_counts_the_missing_teeth_plus_one() {
+        assert_eq!(SixtyMinusTwo::default().gap_period_multiplier(), 3.0);
+        assert_eq!(ThirtySixMinusOne::default().gap_period_multiplier(), 2.0);
+        assert_eq!(EvenTeeth::<36>.gap_period_multiplier(), 1.0);
+    }
+
+    #[test]
+    fn gap_period_multiplier_is_the_measured_gap_ratio() {
+        // Interval over the gap against the first one of the wheel.
+        fn check<P: TriggerPattern + Copy>(pattern: P) {
+            let teeth = pattern.teeth_present() as usize;
+            let mut generator = TickGenerator::new(pattern, 1000.0);
+            let ticks: heapless::Vec<u32, 64> = (0..=teeth)
+                .map(|_| generator.next_tick().unwrap().as_ticks())
+                .collect();
+            let gap = ticks[teeth] - ticks[teeth - 1];
+            let ratio = gap as f64 / (ticks[1] - ticks[0]) as f64;
+            assert!(
+                (ratio - pattern.gap_period_multiplier()).abs() < 0.01,
+                "{ratio}"
+            );
+        }
+
+        check(SixtyMinusTwo::default());
+        check(ThirtySixMinusOne::default());
+    }
+}
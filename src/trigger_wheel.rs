@@ -1,111 +1,2003 @@
-use adskalman::{ObservationModel, TransitionModelLinearNoControl};
+use core::fmt::{self, Write};
+
+use adskalman::{
+    KalmanFilterNoControl, ObservationModel, StateAndCovariance, TransitionModelLinearNoControl,
+};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
 use embassy_time::{Duration, Instant};
 use heapless::HistoryBuffer;
-use nalgebra::{Matrix1, Matrix1x3, Matrix3, Matrix3x1, U1, U3};
+use nalgebra::{Matrix1, OMatrix, OVector, Vector1, U1};
+// Float math for `core`, unused where std provides it.
+#[allow(unused_imports)]
+use nalgebra::ComplexField;
+#[cfg(not(feature = "jerk"))]
+use nalgebra::{Matrix3, U3};
+#[cfg(feature = "jerk")]
+use nalgebra::{Matrix4, U4};
+
+use crate::firing_schedule::FiringSchedule;
+use crate::misfire::Segments;
+use crate::tick::{Tick, TickError};
+use crate::time::{mhz, Hertz};
+use crate::tooth_defect::ToothDeviations;
+use crate::trigger_pattern::{tooth_period, SixtyMinusTwo, TriggerPattern};
+use crate::{debug, error};
 
 #[cfg(feature = "defmt")]
 use defmt::Format;
 
-// Time step for the Kalman filter state transition model
-const DT: f64 = 0.01; // 10 milliseconds
-const DT_SQUARED_HALF: f64 = DT * DT * 0.5;
+// Power spectral density of the white noise driving the highest derivative
+// in the Kalman state, the acceleration (or the jerk), in radians squared
+// per second to the power of 5 (or 7).
+#[cfg(not(feature = "jerk"))]
+const PROCESS_NOISE: f64 = 1.0e6;
+#[cfg(feature = "jerk")]
+const PROCESS_NOISE: f64 = 1.0e7;
+
+// Variance of the angle of a tooth from the machining of the wheel, in
+// radians squared, about a twentieth of a degree.
+const MEASUREMENT_NOISE: f64 = 1.0e-6;
+
+// Dimension of the Kalman state: angle, angular velocity, angular
+// acceleration, and its rate of change with the `jerk` feature.
+#[cfg(not(feature = "jerk"))]
+type KalmanState = U3;
+#[cfg(feature = "jerk")]
+type KalmanState = U4;
+
+// Covariance of a freshly seeded estimate.
+// - The angle is counted from the last tooth, as uncertain as R
+// - The velocity comes from a single interval, to within a few rad/s
+// - Nothing is known about the acceleration (nor the jerk) yet
+#[cfg(not(feature = "jerk"))]
+#[rustfmt::skip]
+const SEED_COVARIANCE: Matrix3<f64> = Matrix3::new(
+    MEASUREMENT_NOISE,  0.0,   0.0,
+                  0.0, 10.0,   0.0,
+                  0.0,  0.0, 1.0e6,
+);
+#[cfg(feature = "jerk")]
+#[rustfmt::skip]
+const SEED_COVARIANCE: Matrix4<f64> = Matrix4::new(
+    MEASUREMENT_NOISE,  0.0,   0.0,   0.0,
+                  0.0, 10.0,   0.0,   0.0,
+                  0.0,  0.0, 1.0e6,   0.0,
+                  0.0,  0.0,   0.0, 1.0e9,
+);
+
+// Input capture timer frequency assumed unless configured otherwise.
+const DEFAULT_TIMER_FREQ: Hertz = mhz(1);
+
+// Width of the input capture timer counter assumed unless configured
+// otherwise, the one of TIM2.
+const DEFAULT_TIMER_BITS: u8 = 32;
+
+/// Engine speed below which the misfire metric is unreliable, in RPM.
+///
+/// Under it the crank speed fluctuates from compression alone about as much
+/// as from combustion, cranking in particular.
+pub const MISFIRE_MIN_RPM: f64 = 500.0;
+
+// An interval at least this many times longer than the baseline is a gap.
+const DEFAULT_GAP_RATIO: f64 = 1.5;
+
+// Number of normal intervals whose median is the baseline gaps are judged
+// against.
+const GAP_BASELINE_WINDOW: usize = 5;
+
+// Largest window `median_interval` sorts, on the stack.
+const MAX_MEDIAN_WINDOW: usize = 16;
+
+// Largest deviation from a whole number of tooth periods for a long interval
+// to be taken as a dropout, in tooth periods.
+const DROPOUT_TOLERANCE: f64 = 0.25;
+
+// Largest deviation of the ratio of two consecutive intervals from the one
+// of their spacings for a wheel resolved by spacing, as a fraction of it.
+const SPACING_TOLERANCE: f64 = 0.25;
+
+// Angular velocity below which the crank is considered stopped, in degrees per second.
+const MIN_ANGULAR_VELOCITY: f64 = 1.0;
 
-pub struct TriggerWheel<const N: usize> {
-    ticks: HistoryBuffer<Instant, N>,
+// Time without a tooth after which the engine is stalled, unless configured
+// otherwise. A 60-2 wheel cranking at 50 RPM takes 60 ms over its gap.
+const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub struct IntervalStats {
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+}
+
+/// Tooth position refused by [`TriggerWheel::force_sync`], either past the
+/// end of the pattern or on a missing tooth.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub struct InvalidTooth;
+
+/// Snapshot of the decoder state, taken by [`TriggerWheel::status`].
+///
+/// Optional fields are `None` whenever the matching accessor returns `None`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub struct DecoderStatus {
+    pub sync: SyncState,
+    pub phase: Option<u8>,
+    pub ticks_since_gap: Option<u16>,
+    pub time_since_gap: Option<Duration>,
+    /// Angle of the last tooth.
+    pub crank_angle: Option<f64>,
+    /// Angle extrapolated at the time of the snapshot.
+    pub angle: Option<f64>,
+    pub angle_uncertainty: Option<f64>,
+    pub rpm: Option<f64>,
+    pub rpm_filtered: Option<f64>,
+    pub misfire_index: Option<f32>,
+    pub dropouts: u32,
+    pub last_dropout: Option<u16>,
+    pub implausible_ticks: u32,
+}
+
+/// Whether the engine turns, and whether it runs on its own, as classified
+/// by [`TriggerWheel::engine_state`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub enum EngineState {
+    /// No tooth for the stall timeout, or never two teeth to measure a speed.
+    Stalled,
+    /// Teeth keep coming, below the cranking ceiling.
+    Cranking,
+    /// Teeth keep coming, at or above the cranking ceiling.
+    Running,
+}
+
+/// Reason for [`TriggerWheel::injection_window`] to give no window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub enum InjectionError {
+    /// The angle is unknown, or the crank stalled.
+    NotSynced,
+    /// The cylinder is not part of the firing schedule.
+    UnknownCylinder,
+    /// The pulse lasts a full cycle at the current speed, it can't fit
+    /// between two injections of the same cylinder.
+    PulseTooLong,
+}
+
+/// Signaled with the instant of the tooth ending each confirmed gap.
+pub type GapSignal = Signal<CriticalSectionRawMutex, Instant>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub enum DecodeMode {
+    /// Crank wheel only, angles cover a single revolution of 360° and
+    /// companion cylinders fire together (wasted spark).
+    #[default]
+    CrankOnly,
+    /// Crank wheel and one cam pulse per cycle, angles cover the 720° cycle.
+    ///
+    /// The cam pulse is expected during the revolution preceding the first
+    /// half of the cycle.
+    Sequential,
 }
 
-impl<const N: usize> TriggerWheel<N> {
+/// Unit of an engine speed returned by [`TriggerWheel::speed`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub enum SpeedUnit {
+    /// Revolutions per minute.
+    Rpm,
+    /// Radians per second.
+    RadPerSec,
+    /// Revolutions per second, that is hertz.
+    RevPerSec,
+}
+
+impl SpeedUnit {
+    /// Converts an angular velocity in degrees per second into this unit.
+    pub fn from_degrees_per_sec(self, velocity: f64) -> f64 {
+        match self {
+            SpeedUnit::Rpm => velocity / 6.0,
+            SpeedUnit::RadPerSec => velocity.to_radians(),
+            SpeedUnit::RevPerSec => velocity / 360.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub enum SyncState {
+    /// Waiting for the reference gap, or for the cam pulse on wheels without one.
+    Searching,
+    /// The position of the last tooth on the wheel is known.
+    Synced,
+}
+
+/// Decodes the tooth edges of a crank trigger wheel.
+///
+/// Speed information only needs teeth, and is available from the second
+/// tooth on, before the reference gap is ever seen:
+/// - `rpm`, `speed`, `rpm_filtered`, `rpm_windowed`, `engine_state`, `is_cranking`
+/// - `intervals`, `interval_stats`, `tooth_velocities`, `write_records`
+/// - `last_was_gap`, `contains_gap`, `latest_tick`, `ticks_count`, `peek_interval`
+/// - `rpm_rate` once two revolutions are buffered
+///
+/// Until synced every interval is taken as a single tooth, so a speed
+/// measured over an unexpected gap reads low.
+///
+/// Position information needs sync and is `None` until then, the cycle
+/// angles needing the cam phase as well in sequential mode:
+/// - `crank_angle_degrees`, `ticks_since_gap`, `time_since_gap`, `phase`
+/// - `angle_at`, `fine_angle`, `next_tooth_at`, `time_to_angle`, `angle_uncertainty_deg`
+/// - `injection_window`, `misfire_index`, `misfire_cylinder`, `torque_proxy`
+/// - `suspect_tooth`
+pub struct TriggerWheel<const N: usize, P = SixtyMinusTwo> {
+    ticks: HistoryBuffer<Edge, N>,
+    pattern: P,
+    mode: DecodeMode,
+    schedule: FiringSchedule,
+    timer_freq: Hertz,
+    // Counter values wrap past it.
+    timer_mask: u64,
+    gap_ratio: f64,
+    // Ratio down to which a gap the pattern expects is still accepted while
+    // synced, at most `gap_ratio`.
+    gap_exit_ratio: f64,
+    // Edges closer than this to the previous accepted one are contact bounce.
+    debounce: Duration,
+    // Time without a tooth after which the engine is stalled.
+    stall_timeout: Duration,
+    // Largest speed increase accepted from one tooth to the next, as a
+    // fraction of the previous speed.
+    max_rpm_step: Option<f64>,
+    // Ticks rejected for implying a larger speed increase.
+    implausible_ticks: u32,
+    gap_signal: Option<&'static GapSignal>,
+    sync: SyncState,
+    // Position of the last tooth, only meaningful when synced.
+    tooth: u16,
+    // Teeth seen since the last gap, only meaningful when synced.
+    teeth_since_gap: u16,
+    // Capture timer value of the tooth ending the last confirmed gap.
+    last_gap: Option<u64>,
+    // Revolution within the 720° cycle, only tracked in sequential mode.
+    revolution: Option<u8>,
+    cam_seen: bool,
+    segments: Segments,
+    deviations: ToothDeviations<N>,
+    // Whether the last interval was long enough to be a gap.
+    last_was_gap: bool,
+    // Runs of missing teeth ridden through while synced.
+    dropouts: u32,
+    // Length of the last of them, in teeth.
+    last_dropout: Option<u16>,
+    // Measured over the last interval, in degrees per second.
+    velocity: Option<f64>,
+    // Kalman estimate of angle, angular velocity and angular acceleration, in
+    // radians, radians per second and radians per second squared, followed by
+    // the jerk with the `jerk` feature. The angle is counted from the last
+    // tooth, so it only holds the error of the estimate at that tooth.
+    estimate: Option<StateAndCovariance<f64, KalmanState>>,
+    // The measurement update runs once every `kalman_decimation` teeth.
+    kalman_decimation: u8,
+    skipped_updates: u8,
+}
+
+// An accepted tooth edge, as decoded when it came.
+#[derive(Clone, Copy)]
+struct Edge {
+    // Capture timer value, at `timer_freq`. Times only turn into embassy
+    // instants and durations at the API, the 10 µs time base being far
+    // coarser than the capture timer.
+    tick: u64,
+    // Position of the tooth, only known when synced.
+    position: Option<u16>,
+    // Tooth positions covered by the interval ending on the edge, more than
+    // one over a gap or a dropout. Always `1` while searching.
+    span: u16,
+}
+
+impl<const N: usize, P: TriggerPattern + Default> TriggerWheel<N, P> {
     pub fn new() -> Self {
+        Self::with_pattern(P::default())
+    }
+}
+
+impl<const N: usize, P: TriggerPattern + Default> Default for TriggerWheel<N, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, P: TriggerPattern> TriggerWheel<N, P> {
+    /// The pattern must have tooth positions, and the history must hold more
+    /// ticks than it has, which fails the build for patterns known at compile
+    /// time. A pattern only known at run time panics instead, in release
+    /// builds too, where [`TriggerWheelBuilder::build`] reports an error.
+    pub fn with_pattern(pattern: P) -> Self {
+        const {
+            if let Some(teeth) = P::TEETH_PER_REV {
+                assert!(teeth > 0, "The pattern must have tooth positions");
+                assert!(
+                    N > teeth as usize,
+                    "The tick history must hold a full revolution of the pattern"
+                );
+            }
+        }
+        assert!(
+            pattern.teeth_per_rev() > 0,
+            "The pattern must have tooth positions"
+        );
+        assert!(
+            N > pattern.teeth_per_rev() as usize,
+            "The tick history must hold a full revolution of the pattern"
+        );
+
         Self {
             ticks: HistoryBuffer::new(),
+            pattern,
+            mode: DecodeMode::default(),
+            schedule: FiringSchedule::default(),
+            timer_freq: DEFAULT_TIMER_FREQ,
+            timer_mask: timer_mask(DEFAULT_TIMER_BITS),
+            gap_ratio: DEFAULT_GAP_RATIO,
+            gap_exit_ratio: DEFAULT_GAP_RATIO,
+            debounce: Duration::from_ticks(0),
+            stall_timeout: DEFAULT_STALL_TIMEOUT,
+            max_rpm_step: None,
+            implausible_ticks: 0,
+            gap_signal: None,
+            sync: SyncState::Searching,
+            tooth: 0,
+            teeth_since_gap: 0,
+            last_gap: None,
+            revolution: None,
+            cam_seen: false,
+            segments: Segments::new(),
+            deviations: ToothDeviations::new(),
+            last_was_gap: false,
+            dropouts: 0,
+            last_dropout: None,
+            velocity: None,
+            estimate: None,
+            kalman_decimation: 1,
+            skipped_updates: 0,
         }
     }
 
-    pub fn add_tick(&mut self, tick: &Instant) -> Option<Duration> {
-        let interval = self
-            .ticks
-            .recent()
-            .and_then(|recent_tick| match recent_tick {
-                _ if recent_tick <= tick => tick.checked_duration_since(*recent_tick),
-                _ => recent_tick.checked_duration_since(*tick),
-            });
+    /// Sets the interval ratio declaring a gap, without hysteresis.
+    pub fn with_gap_ratio(mut self, gap_ratio: f64) -> Self {
+        self.gap_ratio = gap_ratio;
+        self.gap_exit_ratio = gap_ratio;
+        self
+    }
+
+    /// Keeps accepting the gaps the pattern expects while synced down to
+    /// `exit_ratio`, below the ratio needed to find a gap in the first place.
+    ///
+    /// This keeps sync from flapping at speeds where jitter moves the measured
+    /// ratio around the threshold, while unexpected gaps still need the full
+    /// ratio.
+    pub fn with_gap_hysteresis(mut self, exit_ratio: f64) -> Self {
+        self.gap_exit_ratio = exit_ratio.min(self.gap_ratio);
+        self
+    }
+
+    /// Frequency of the timer capturing the ticks fed to `add_tick`.
+    pub fn with_timer_freq(mut self, freq: Hertz) -> Self {
+        self.timer_freq = freq;
+        self
+    }
+
+    /// Width of the counter of the timer capturing the ticks fed to
+    /// `add_tick`, 32 bits unless set, at most 32.
+    ///
+    /// Intervals are measured across counter wraps, and must last less than
+    /// half the counter period: 35 minutes for 32 bits at 1 MHz, but only
+    /// 32 ms for 16 bits.
+    pub fn with_timer_bits(mut self, bits: u8) -> Self {
+        self.timer_mask = timer_mask(bits);
+        self
+    }
+
+    pub fn with_decode_mode(mut self, mode: DecodeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_firing_schedule(mut self, schedule: FiringSchedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Ignores edges arriving within `min` of the previous accepted one.
+    ///
+    /// This is a fixed guard against electrical bounce on the sensor line,
+    /// independent of the engine speed.
+    pub fn with_debounce(mut self, min: Duration) -> Self {
+        self.debounce = min;
+        self
+    }
+
+    /// Takes the engine as stalled once no tooth came for `timeout`, see
+    /// `engine_state`. Half a second unless set.
+    ///
+    /// It has to outlast the longest interval of the slowest cranking, the
+    /// one over the gap included.
+    pub fn with_stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = timeout;
+        self
+    }
+
+    /// Rejects ticks implying the crank sped up by more than `fraction` of
+    /// its speed since the previous tooth, e.g. `0.5` for 50%.
+    ///
+    /// The crank can't physically go from 3000 to 9000 RPM within a tooth, so
+    /// such an interval comes from a spurious edge. Rejected ticks are counted
+    /// by `implausible_ticks` and not recorded, the next genuine edge is then
+    /// measured from the last accepted one. Intervals implying a slowdown are
+    /// left to the gap and dropout detection. While searching the speed is
+    /// the median one of the last few teeth, and the first few go unchecked,
+    /// as do the intervals expected to be a gap while synced.
+    pub fn with_max_rpm_step(mut self, fraction: f64) -> Self {
+        self.max_rpm_step = Some(fraction);
+        self
+    }
+
+    /// Signals `signal` on every gap found where the pattern expects one
+    /// while synced, the gap that acquires sync is not confirmed yet.
+    pub fn with_gap_signal(mut self, signal: &'static GapSignal) -> Self {
+        self.gap_signal = Some(signal);
+        self
+    }
+
+    /// Records a tooth edge and returns the interval since the previous one,
+    /// none for the first edge and for edges ignored as bounce.
+    ///
+    /// An edge at the same instant as the previous one is rejected as a
+    /// duplicate capture and leaves the decoder untouched, as is an edge
+    /// beyond the limit set by `with_max_rpm_step`. The counter may wrap in
+    /// between, see `with_timer_bits`, an edge over half its range behind the
+    /// previous one is rejected as out of order.
+    pub fn add_tick(&mut self, tick: Tick) -> Result<Option<Duration>, TickError> {
+        self.record(self.unwrap_tick(tick)?)
+    }
+
+    /// Interval `add_tick` would measure for `tick`, without recording it.
+    ///
+    /// Lets the tick be checked before committing it. A duplicate shows as a
+    /// zero interval, which `add_tick` rejects, and an out of order tick as
+    /// none.
+    pub fn peek_interval(&self, tick: Tick) -> Option<Duration> {
+        let tick = self.unwrap_tick(tick).ok()?;
+        self.last_tick()
+            .map(|recent_tick| self.duration(interval_between(recent_tick, tick)))
+    }
+
+    /// Records a tooth edge timestamped elsewhere, e.g. by another MCU or a
+    /// timestamping transceiver, bypassing the timer conversion of `add_tick`.
+    ///
+    /// The instant is taken as the value a timer at `timer_freq` would have
+    /// captured, so it is only as fine as the embassy time base. Everything
+    /// else, duplicates and bounce included, is handled as in `add_tick`. An
+    /// instant before the previous one is rejected as out of order.
+    pub fn add_tick_at(&mut self, tick: Instant) -> Result<Option<Duration>, TickError> {
+        let tick = self.timer_ticks(tick.as_micros());
+        if self.last_tick().is_some_and(|last| tick < last) {
+            return Err(TickError::OutOfOrder);
+        }
+        self.record(tick)
+    }
+
+    // Records the tooth edge at the timer value `tick`, counted on from the
+    // previous one without wrapping.
+    fn record(&mut self, tick: u64) -> Result<Option<Duration>, TickError> {
+        let interval = match self.last_tick() {
+            Some(recent_tick) if recent_tick == tick => return Err(TickError::Duplicate),
+            Some(recent_tick) => Some(interval_between(recent_tick, tick)),
+            None => None,
+        };
+
+        let debounce = self.timer_ticks(self.debounce.as_micros());
+        if interval.is_some_and(|interval| interval < debounce) {
+            return Ok(None);
+        }
+
+        if interval.is_some_and(|interval| self.is_implausible(interval)) {
+            self.implausible_ticks = self.implausible_ticks.saturating_add(1);
+            return Err(TickError::Implausible);
+        }
+
+        // Taken before the interval joins the history it is compared with.
+        let baseline = self.median_ticks(GAP_BASELINE_WINDOW);
+        let (position, span) = match interval {
+            Some(interval) => self.track(tick, interval, baseline),
+            None => (None, 1),
+        };
+        self.ticks.write(Edge {
+            tick,
+            position,
+            span,
+        });
+
+        Ok(interval.map(|interval| self.duration(interval)))
+    }
+
+    // Whether the interval implies a larger speed increase over the previous
+    // one than allowed.
+    fn is_implausible(&self, interval: u64) -> bool {
+        let Some(max_step) = self.max_rpm_step else {
+            return false;
+        };
+
+        // While searching the last interval may be a gap, its speed off by the
+        // missing teeth, so the median of a few intervals is compared with
+        // instead. Any speed measured on a wheel resolved by spacing is off by
+        // the spacing, there is nothing to compare with.
+        //
+        // Nor is an interval expected to be a gap checked: a tooth coming a
+        // period after the last one there shows a wrong position, which the
+        // sync has to be lost on rather than the tooth rejected.
+        let velocity = match self.sync {
+            SyncState::Synced if self.expects_gap() => return false,
+            SyncState::Synced => self.velocity,
+            SyncState::Searching if self.pattern.resolved_by_spacing() => return false,
+            SyncState::Searching if self.ticks.len() <= GAP_BASELINE_WINDOW => return false,
+            SyncState::Searching => self
+                .median_ticks(GAP_BASELINE_WINDOW)
+                .map(|period| self.interval_velocity(1, period)),
+        };
+        let Some(velocity) = velocity else {
+            return false;
+        };
+
+        let seconds = self.seconds(interval);
+        let limit = velocity * (1.0 + max_step);
+        seconds <= 0.0 || self.degrees_per_tooth() > limit * seconds
+    }
+
+    // Whether a gap comes next on the wheel, once synced.
+    fn expects_gap(&self) -> bool {
+        let next = self.pattern.next_tooth(self.tooth);
+        !self.pattern.gaps().is_empty() && self.distance(self.tooth, next) > 1
+    }
+
+    // Timer value of `tick` counted on from the last accepted tooth, across
+    // any wraps of the counter since.
+    fn unwrap_tick(&self, tick: Tick) -> Result<u64, TickError> {
+        let tick = tick.as_ticks() as u64 & self.timer_mask;
+        let Some(last) = self.last_tick() else {
+            return Ok(tick);
+        };
+
+        let elapsed = tick.wrapping_sub(last) & self.timer_mask;
+        match elapsed > self.timer_mask / 2 {
+            true => Err(TickError::OutOfOrder),
+            false => Ok(last + elapsed),
+        }
+    }
+
+    // Advances the sync state machine by one tooth, gaps being judged
+    // against the `baseline` tooth period. Returns the position of the tooth
+    // and the positions covered by the interval, for the history.
+    fn track(&mut self, tick: u64, interval: u64, baseline: Option<u64>) -> (Option<u16>, u16) {
+        let has_gaps = !self.pattern.gaps().is_empty();
+        let is_gap =
+            |ratio| has_gaps && baseline.is_some_and(|period| is_gap(interval, period, ratio));
+
+        // Number of tooth positions covered by the interval, a single one
+        // unless the position on the wheel says otherwise.
+        let mut span = 1;
+
+        self.last_was_gap = match self.sync {
+            _ if self.pattern.resolved_by_spacing() => {
+                let reference;
+                (span, reference) = self.track_spacing(tick, interval);
+                reference
+            }
+            SyncState::Searching => {
+                let is_gap = is_gap(self.gap_ratio);
+                // Wheels without a gap are synced by the cam pulse instead,
+                // the tooth following it being the reference one.
+                let found = match has_gaps {
+                    true => is_gap.then_some(self.pattern.gap_period_multiplier() as u16),
+                    false => self.cam_seen.then_some(1),
+                };
+
+                if let Some(found_span) = found {
+                    let reference = self.reference_tooth();
+                    span = found_span;
+                    self.tooth = reference;
+                    self.teeth_since_gap = 0;
+                    self.sync = SyncState::Synced;
+                    debug!("Synced at tooth {}", reference);
+                    self.track_phase();
+                }
+                is_gap
+            }
+            SyncState::Synced => {
+                let mut next = self.pattern.next_tooth(self.tooth);
+                // On wheels without a gap the tooth following the cam pulse
+                // is the reference one, whatever position was expected.
+                if !has_gaps && self.cam_seen && next != self.reference_tooth() {
+                    debug!("Realigned on the cam pulse at tooth {}", next);
+                    next = self.reference_tooth();
+                }
+                let distance = match has_gaps {
+                    true => self.distance(self.tooth, next),
+                    false => 1,
+                };
+                let expect_gap = distance > 1;
+                let is_gap = match expect_gap {
+                    true => is_gap(self.gap_exit_ratio),
+                    false => is_gap(self.gap_ratio),
+                };
+                // An expected gap lasting longer than its positions lost the
+                // tooth after it too.
+                let overlong = expect_gap
+                    && baseline.is_some_and(|period| {
+                        interval as f64 > (distance as f64 + DROPOUT_TOLERANCE) * period as f64
+                    });
+                let dropout = match is_gap && (!expect_gap || overlong) {
+                    true => baseline.and_then(|period| self.dropout(interval, period)),
+                    false => None,
+                };
+
+                if expect_gap == is_gap && !overlong {
+                    let reference = next == self.reference_tooth();
+                    span = distance;
+                    self.tooth = next;
+                    self.teeth_since_gap = match is_gap || reference {
+                        true => 0,
+                        false => self.teeth_since_gap + 1,
+                    };
+
+                    if is_gap {
+                        self.last_gap = Some(tick);
+                        if let Some(signal) = self.gap_signal {
+                            signal.signal(self.instant(tick));
+                        }
+                    }
+
+                    if reference {
+                        self.track_phase();
+                    }
+                } else if let Some((missed, covered)) = dropout {
+                    let landed = (self.tooth + covered) % self.pattern.teeth_per_rev();
+                    let reference = self.distance(self.tooth, self.reference_tooth());
+                    span = covered;
+                    self.tooth = landed;
+                    self.teeth_since_gap = self.teeth_after_gap(landed);
+                    self.dropouts = self.dropouts.saturating_add(1);
+                    self.last_dropout = Some(missed);
+                    debug!("Dropout of {} teeth before tooth {}", missed, self.tooth);
+
+                    // The cam phase moves on with a reference tooth dropped.
+                    if (1..=covered).contains(&reference) {
+                        self.track_phase();
+                    }
+                } else {
+                    self.lose_sync();
+                    debug!("Sync lost at tooth {}, gap: {}", self.tooth, is_gap);
+                }
+                is_gap
+            }
+        };
+
+        let seconds = self.seconds(interval);
+        if seconds > 0.0 {
+            let degrees = span as f64 * self.degrees_per_tooth();
+            self.velocity = Some(degrees / seconds);
+            self.filter(degrees.to_radians(), seconds);
+        }
+
+        self.track_segments(interval, span);
+        if let Some(period) = baseline {
+            self.track_deviations(interval, span, period);
+        }
+
+        match self.sync {
+            SyncState::Synced => (Some(self.tooth), span),
+            SyncState::Searching => (None, 1),
+        }
+    }
+
+    // Advances the sync state machine of a wheel resolved by spacing, the
+    // ratio of the interval to the previous one telling the tooth. Returns the
+    // tooth positions covered by the interval and whether it ends on the
+    // reference tooth, the only gap confirmed on such wheels.
+    fn track_spacing(&mut self, tick: u64, interval: u64) -> (u16, bool) {
+        // The tick isn't recorded yet, the last buffered interval precedes it.
+        let Some((_, previous)) = self.recent_tooth_intervals().next() else {
+            return (1, false);
+        };
+        let ratio = interval as f64 / previous as f64;
 
-        self.ticks.write(*tick);
+        // Whether the ratio is the one of the spacings before `position`.
+        let matches = |position: u16| {
+            let before = self.pattern.prev_tooth(position);
+            let spacing = self.distance(before, position) as f64;
+            let previous_spacing = self.distance(self.pattern.prev_tooth(before), before) as f64;
+            (ratio * previous_spacing / spacing - 1.0).abs() <= SPACING_TOLERANCE
+        };
 
-        interval
+        let found = match self.sync {
+            SyncState::Synced => Some(self.pattern.next_tooth(self.tooth)).filter(|&p| matches(p)),
+            SyncState::Searching => {
+                let mut candidates = (0..self.pattern.teeth_per_rev())
+                    .filter(|&p| !self.pattern.is_missing(p) && matches(p));
+                match (candidates.next(), candidates.next()) {
+                    (Some(position), None) => Some(position),
+                    _ => None,
+                }
+            }
+        };
+
+        let Some(position) = found else {
+            if self.sync == SyncState::Synced {
+                self.lose_sync();
+                debug!(
+                    "Sync lost at tooth {}, spacing ratio: {}",
+                    self.tooth, ratio
+                );
+            }
+            return (1, false);
+        };
+
+        let reference = position == self.reference_tooth();
+        if self.sync == SyncState::Synced && reference {
+            self.last_gap = Some(tick);
+            if let Some(signal) = self.gap_signal {
+                signal.signal(self.instant(tick));
+            }
+        }
+        if self.sync == SyncState::Searching {
+            self.sync = SyncState::Synced;
+            debug!("Synced at tooth {}", position);
+        }
+
+        self.tooth = position;
+        self.teeth_since_gap = self.teeth_from_reference(position);
+        if reference {
+            self.track_phase();
+        }
+
+        let span = self.distance(self.pattern.prev_tooth(position), position);
+        (span, reference)
+    }
+
+    fn lose_sync(&mut self) {
+        self.sync = SyncState::Searching;
+        self.last_gap = None;
+        self.revolution = None;
+        self.segments.invalidate();
+        self.deviations.invalidate();
+    }
+
+    // Runs a Kalman step over the `dt` seconds the crank took to turn by
+    // `increment` radians, from the last tooth to this one.
+    //
+    // What is measured is the angle of the tooth at its arrival time, which
+    // is the angle the state holds once predicted over `dt`. The angle is
+    // then counted from this tooth again, subtracting a known constant that
+    // leaves the covariance alone.
+    //
+    // The first measurement seeds the estimate instead, so it doesn't have to
+    // ramp up from a standstill.
+    fn filter(&mut self, increment: f64, dt: f64) {
+        let Some(estimate) = &self.estimate else {
+            self.seed(increment / dt);
+            return;
+        };
+
+        // Skipped updates go through as a missing observation, leaving the
+        // prediction alone.
+        self.skipped_updates = (self.skipped_updates + 1) % self.kalman_decimation;
+        let observation = match self.skipped_updates {
+            0 => increment,
+            _ => f64::NAN,
+        };
+
+        let motion = CrankMotion::new(dt);
+        let arrival = ToothArrival::new(estimate.state()[1], self.timer_freq);
+        let kf = KalmanFilterNoControl::new(&motion, &arrival);
+        match kf.step(estimate, &Vector1::new(observation)) {
+            Ok(mut estimate) => {
+                estimate.state_mut()[0] -= increment;
+                self.estimate = Some(estimate);
+            }
+            Err(_) => {
+                error!("Kalman step failed, reseeding");
+                self.seed(increment / dt);
+            }
+        }
+    }
+
+    fn seed(&mut self, velocity: f64) {
+        let mut state = OVector::<f64, KalmanState>::zeros();
+        state[1] = velocity;
+        self.estimate = Some(StateAndCovariance::new(state, SEED_COVARIANCE));
+    }
+
+    /// Runs the Kalman measurement update on every `k`th tooth only, the
+    /// prediction still running on every tooth.
+    ///
+    /// The update costs more than the prediction, so this frees CPU time at
+    /// the expense of a filtered estimate lagging by up to `k` teeth. The raw
+    /// speed, gap detection and sync keep following every tooth. A `k` of `0`
+    /// is taken as `1`, updating on every tooth. `bench::measure` gives the
+    /// cost on target.
+    pub fn set_kalman_decimation(&mut self, k: u8) {
+        self.kalman_decimation = k.max(1);
+        self.skipped_updates = 0;
+    }
+
+    /// Sets the estimated engine speed, e.g. from the expected cranking speed
+    /// before the first interval is measured.
+    ///
+    /// The estimate is otherwise seeded from the first measured interval.
+    pub fn seed_state(&mut self, initial_rpm: f64) {
+        self.seed((initial_rpm * 6.0).to_radians());
+    }
+
+    /// Reseeds the Kalman estimate from the speed measured over the last
+    /// interval, e.g. once it diverged after a transient.
+    ///
+    /// Unlike `reset`, the tick history, the sync and the position on the
+    /// wheel are kept. Without any interval measured yet the estimate is
+    /// dropped, to be seeded by the next one.
+    pub fn reset_filter(&mut self) {
+        self.skipped_updates = 0;
+        match self.velocity {
+            Some(velocity) => self.seed(velocity.to_radians()),
+            None => self.estimate = None,
+        }
+    }
+
+    // Accounts for the interval in the power stroke segments of the cycle.
+    fn track_segments(&mut self, interval: u64, span: u16) {
+        let Some(angle) = self.crank_angle_degrees() else {
+            return;
+        };
+
+        let mut start = angle - span as f64 * self.degrees_per_tooth();
+        if start < 0.0 {
+            start += self.cycle_degrees();
+        }
+        self.segments
+            .record(&self.schedule, start, self.micros(interval));
+
+        if self.tooth == self.reference_tooth() && self.revolution == Some(0) {
+            self.segments.start_cycle(&self.schedule);
+        }
+    }
+
+    // Accounts for the deviation of the interval from `span` tooth periods.
+    fn track_deviations(&mut self, interval: u64, span: u16, period: u64) {
+        if self.sync != SyncState::Synced {
+            return;
+        }
+
+        let expected = span as f64 * period as f64;
+        let deviation = interval as f64 / expected - 1.0;
+        self.deviations.record(self.tooth, deviation as f32);
+
+        if self.tooth == self.reference_tooth() {
+            self.deviations.complete_revolution();
+        }
+    }
+
+    // Moves on to the next revolution of the cycle at the reference tooth.
+    fn track_phase(&mut self) {
+        if self.mode != DecodeMode::Sequential {
+            return;
+        }
+
+        self.revolution = match (self.cam_seen, self.revolution) {
+            (true, _) => Some(0),
+            (false, Some(0)) => Some(1),
+            // Either no cam pulse so far, or one went missing.
+            (false, _) => None,
+        };
+        self.cam_seen = false;
+
+        if self.revolution.is_none() {
+            self.segments.invalidate();
+        }
+    }
+
+    /// Records a cam pulse, ignored unless in sequential mode.
+    pub fn add_cam_tick(&mut self) {
+        if self.mode == DecodeMode::Sequential {
+            self.cam_seen = true;
+        }
+    }
+
+    /// Declares the last tooth to be at `tooth_index`, overriding the sync
+    /// found from the gap, e.g. for static timing checks with the crank at a
+    /// known position.
+    ///
+    /// The position must hold a tooth of the pattern. The cam phase is left
+    /// alone, in sequential mode the angle still needs it.
+    pub fn force_sync(&mut self, tooth_index: u16) -> Result<(), InvalidTooth> {
+        if tooth_index >= self.pattern.teeth_per_rev() || self.pattern.is_missing(tooth_index) {
+            return Err(InvalidTooth);
+        }
+
+        self.tooth = tooth_index;
+        self.teeth_since_gap = self.teeth_from_reference(tooth_index);
+        self.sync = SyncState::Synced;
+        self.segments.invalidate();
+        self.deviations.invalidate();
+        debug!("Sync forced at tooth {}", tooth_index);
+        Ok(())
+    }
+
+    // Teeth from the reference one forward to the one at `position`.
+    fn teeth_from_reference(&self, position: u16) -> u16 {
+        let mut teeth = 0;
+        let mut tooth = self.reference_tooth();
+        while tooth != position {
+            tooth = self.pattern.next_tooth(tooth);
+            teeth += 1;
+        }
+        teeth
+    }
+
+    // Position of the tooth that follows the reference gap, `0` on wheels
+    // without any gap.
+    fn reference_tooth(&self) -> u16 {
+        self.pattern.gaps().first().map_or(0, |gap| {
+            (gap.position + gap.width) % self.pattern.teeth_per_rev()
+        })
+    }
+
+    // Number of teeth missed by an unexpectedly long interval while synced,
+    // along with the positions it covers, when it lasts a whole number of
+    // tooth periods, ends on a tooth and skips some. Gaps may lie in between.
+    // Anything else can't be told from a lost sync.
+    fn dropout(&self, interval: u64, period: u64) -> Option<(u16, u16)> {
+        let teeth = self.pattern.teeth_per_rev();
+        let ratio = interval as f64 / period as f64;
+        let periods = (ratio + 0.5) as u16;
+        if periods >= teeth
+            || !(-DROPOUT_TOLERANCE..=DROPOUT_TOLERANCE).contains(&(ratio - periods as f64))
+            || self.pattern.is_missing((self.tooth + periods) % teeth)
+        {
+            return None;
+        }
+
+        let missed = (1..periods)
+            .filter(|k| !self.pattern.is_missing((self.tooth + k) % teeth))
+            .count() as u16;
+        (missed > 0).then_some((missed, periods))
+    }
+
+    // Teeth from the last gap or reference tooth to the one at `position`.
+    fn teeth_after_gap(&self, position: u16) -> u16 {
+        let mut teeth = 0;
+        let mut tooth = position;
+        while tooth != self.reference_tooth() {
+            let before = self.pattern.prev_tooth(tooth);
+            if self.distance(before, tooth) > 1 {
+                break;
+            }
+            tooth = before;
+            teeth += 1;
+        }
+        teeth
+    }
+
+    // Number of tooth positions from `from` forward to `to`.
+    fn distance(&self, from: u16, to: u16) -> u16 {
+        let teeth = self.pattern.teeth_per_rev();
+        (to + teeth - from) % teeth
     }
 
     pub fn ticks_count(&self) -> usize {
         self.ticks.len()
     }
+
+    /// Most recently accepted tick.
+    ///
+    /// This is the captured value, or the one a timer at `timer_freq` would
+    /// have captured for an edge given to `add_tick_at`, wrapping with the
+    /// timer counter.
+    pub fn latest_tick(&self) -> Option<Tick> {
+        self.last_tick()
+            .map(|tick| Tick::from_ticks((tick & self.timer_mask) as u32))
+    }
+
+    // Capture timer value of the last accepted tooth.
+    fn last_tick(&self) -> Option<u64> {
+        self.ticks.recent().map(|edge| edge.tick)
+    }
+
+    /// Intervals between the buffered ticks, oldest first.
+    pub fn intervals(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.timer_intervals()
+            .map(|interval| self.duration(interval))
+    }
+
+    // Intervals between the buffered ticks, oldest first, in timer ticks.
+    fn timer_intervals(&self) -> impl Iterator<Item = u64> + '_ {
+        self.ticks
+            .oldest_ordered()
+            .zip(self.ticks.oldest_ordered().skip(1))
+            .map(|(earlier, later)| interval_between(earlier.tick, later.tick))
+    }
+
+    // Buffered intervals, oldest first, along with the edge ending each of
+    // them and its position, as decoded when it came.
+    fn tooth_intervals(&self) -> impl Iterator<Item = (Edge, u64)> + '_ {
+        self.ticks
+            .oldest_ordered()
+            .zip(self.ticks.oldest_ordered().skip(1))
+            .map(|(earlier, &later)| (later, interval_between(earlier.tick, later.tick)))
+    }
+
+    // Buffered intervals, newest first, along with the edge ending each of
+    // them.
+    fn recent_tooth_intervals(&self) -> impl Iterator<Item = (Edge, u64)> + '_ {
+        let (older, newer) = self.ticks.as_slices();
+        let edges = || newer.iter().rev().chain(older.iter().rev());
+
+        edges()
+            .zip(edges().skip(1))
+            .map(|(&later, earlier)| (later, interval_between(earlier.tick, later.tick)))
+    }
+
+    /// Median of the last `window` normal intervals, the baseline gaps are
+    /// judged against.
+    ///
+    /// A single noisy interval moves the median much less than it moves the
+    /// previous interval alone. When synced, intervals spanning a gap are left
+    /// out. The window is clamped to the buffered intervals and to 16, and an
+    /// even window averages the two middle intervals.
+    pub fn median_interval(&self, window: usize) -> Option<Duration> {
+        self.median_ticks(window)
+            .map(|interval| self.duration(interval))
+    }
+
+    // `median_interval` in timer ticks.
+    fn median_ticks(&self, window: usize) -> Option<u64> {
+        let teeth = self
+            .recent_tooth_intervals()
+            .filter(|(edge, _)| edge.span == 1)
+            .map(|(_, interval)| interval)
+            .take(window);
+        median(teeth)
+    }
+
+    /// Whether the buffered ticks span a gap of the wheel.
+    ///
+    /// Every buffered interval is judged as `add_tick` would, against the
+    /// gap ratio and the median of the intervals before it, so this holds
+    /// while searching too. Outputs that need a full revolution of data can't
+    /// be trusted before it does. Always `false` for wheels without a gap.
+    pub fn contains_gap(&self) -> bool {
+        if self.pattern.gaps().is_empty() {
+            return false;
+        }
+
+        // The last few normal intervals before the one judged.
+        let mut preceding = HistoryBuffer::<u64, GAP_BASELINE_WINDOW>::new();
+        self.tooth_intervals().any(|(edge, interval)| {
+            let baseline = median(preceding.iter().copied());
+            if edge.span == 1 {
+                preceding.write(interval);
+            }
+            baseline.is_some_and(|period| is_gap(interval, period, self.gap_ratio))
+        })
+    }
+
+    /// Minimum, maximum and mean of the buffered intervals.
+    ///
+    /// When synced, intervals spanning a gap are left out so the statistics
+    /// describe a single tooth.
+    pub fn interval_stats(&self) -> Option<IntervalStats> {
+        let mut stats: Option<(u64, u64)> = None;
+        let mut total: u64 = 0;
+        let mut count: u64 = 0;
+
+        let teeth = self.tooth_intervals().filter(|(edge, _)| edge.span == 1);
+
+        for (_, interval) in teeth {
+            total += interval;
+            count += 1;
+
+            stats = Some(match stats {
+                Some((min, max)) => (min.min(interval), max.max(interval)),
+                None => (interval, interval),
+            });
+        }
+
+        stats.map(|(min, max)| IntervalStats {
+            min: self.duration(min),
+            max: self.duration(max),
+            mean: self.duration(total / count),
+        })
+    }
+
+    /// Writes the buffered intervals, oldest first, one line each:
+    ///
+    /// ```text
+    /// <tick>,<interval µs>,<rpm>
+    /// ```
+    ///
+    /// The tick is the one ending the interval and the speed has one decimal.
+    /// When synced, the speed over a gap accounts for the missing teeth.
+    pub fn write_records<W: Write>(&self, w: &mut W) -> fmt::Result {
+        for (edge, interval) in self.tooth_intervals() {
+            writeln!(
+                w,
+                "{},{},{:.1}",
+                edge.tick as u32,
+                self.micros(interval),
+                self.interval_velocity(edge.span, interval) / 6.0
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Angular velocity over each buffered interval, oldest first, in degrees
+    /// per second, along with the position of the tooth ending it.
+    ///
+    /// Positions are only known for teeth recorded while synced, and the
+    /// velocity over a gap or a dropout then accounts for the missing teeth.
+    /// Otherwise every interval is taken as a single tooth and positions are
+    /// `None`.
+    pub fn tooth_velocities(&self) -> impl Iterator<Item = (Option<u16>, f64)> + '_ {
+        self.tooth_intervals()
+            .map(|(edge, interval)| (edge.position, self.interval_velocity(edge.span, interval)))
+    }
+
+    // Angular velocity over an interval covering `span` tooth positions, in
+    // degrees per second.
+    fn interval_velocity(&self, span: u16, interval: u64) -> f64 {
+        let seconds = self.seconds(interval);
+        match seconds > 0.0 {
+            true => span as f64 * self.degrees_per_tooth() / seconds,
+            false => 0.0,
+        }
+    }
+
+    pub fn pattern(&self) -> &P {
+        &self.pattern
+    }
+
+    /// Angle between two consecutive tooth positions of the pattern, in
+    /// degrees, see [`TriggerPattern::degrees_per_tooth`].
+    pub fn degrees_per_tooth(&self) -> f64 {
+        self.pattern.degrees_per_tooth()
+    }
+
+    pub fn decode_mode(&self) -> DecodeMode {
+        self.mode
+    }
+
+    pub fn firing_schedule(&self) -> &FiringSchedule {
+        &self.schedule
+    }
+
+    pub fn sync_state(&self) -> SyncState {
+        self.sync
+    }
+
+    /// Whether the last interval was a gap, as judged from its ratio to the
+    /// median of the normal intervals before it.
+    ///
+    /// Unexpected gaps count too, be they dropouts or a lost sync. Always
+    /// `false` until two intervals have been measured.
+    pub fn last_was_gap(&self) -> bool {
+        self.last_was_gap
+    }
+
+    /// Number of signal dropouts ridden through while synced.
+    ///
+    /// A dropout is an interval lasting a few tooth periods where the pattern
+    /// expects teeth, as from intermittent wiring. The position is carried
+    /// over it instead of dropping sync.
+    pub fn dropouts(&self) -> u32 {
+        self.dropouts
+    }
+
+    /// Number of ticks rejected for an implausible speed increase, see
+    /// `with_max_rpm_step`.
+    pub fn implausible_ticks(&self) -> u32 {
+        self.implausible_ticks
+    }
+
+    /// Number of teeth missed by the last dropout.
+    pub fn last_dropout(&self) -> Option<u16> {
+        self.last_dropout
+    }
+
+    /// Snapshot of the decoder state at `now`, consistent since it is taken
+    /// from a single borrow.
+    pub fn status(&self, now: Instant) -> DecoderStatus {
+        DecoderStatus {
+            sync: self.sync_state(),
+            phase: self.phase(),
+            ticks_since_gap: self.ticks_since_gap(),
+            time_since_gap: self.time_since_gap(now),
+            crank_angle: self.crank_angle_degrees(),
+            angle: self.angle_at(now),
+            angle_uncertainty: self.angle_uncertainty_deg(now),
+            rpm: self.rpm(),
+            rpm_filtered: self.rpm_filtered(),
+            misfire_index: self.misfire_index(),
+            dropouts: self.dropouts(),
+            last_dropout: self.last_dropout(),
+            implausible_ticks: self.implausible_ticks(),
+        }
+    }
+
+    /// Number of teeth since the last gap, `0` on the tooth right after it.
+    pub fn ticks_since_gap(&self) -> Option<u16> {
+        match self.sync {
+            SyncState::Synced => Some(self.teeth_since_gap),
+            SyncState::Searching => None,
+        }
+    }
+
+    /// Time elapsed at `now` since the tooth ending the last confirmed gap.
+    ///
+    /// `None` until a gap is found where the pattern expects one while
+    /// synced, the same gaps `with_gap_signal` signals.
+    pub fn time_since_gap(&self, now: Instant) -> Option<Duration> {
+        now.checked_duration_since(self.instant(self.last_gap?))
+    }
+
+    /// Revolution within the 720° cycle, `0` or `1`, once the cam phase is known.
+    ///
+    /// Always `None` in crank-only mode.
+    pub fn phase(&self) -> Option<u8> {
+        match self.sync {
+            SyncState::Synced => self.revolution,
+            SyncState::Searching => None,
+        }
+    }
+
+    /// Angle of the last tooth after the reference gap, in degrees.
+    ///
+    /// Covers 360° in crank-only mode, and 720° in sequential mode where it
+    /// also needs the cam phase.
+    pub fn crank_angle_degrees(&self) -> Option<f64> {
+        if self.sync != SyncState::Synced {
+            return None;
+        }
+
+        let angle = self.tooth as f64 * self.degrees_per_tooth();
+        match self.mode {
+            DecodeMode::CrankOnly => Some(angle),
+            DecodeMode::Sequential => self.revolution.map(|r| angle + 360.0 * r as f64),
+        }
+    }
+
+    /// Engine speed measured over the last interval.
+    pub fn rpm(&self) -> Option<f64> {
+        self.speed(SpeedUnit::Rpm)
+    }
+
+    /// Engine speed measured over the last interval, in `unit`.
+    pub fn speed(&self, unit: SpeedUnit) -> Option<f64> {
+        self.velocity
+            .map(|velocity| unit.from_degrees_per_sec(velocity))
+    }
+
+    /// Engine speed averaged over the last `window` tooth intervals, the most
+    /// recent weighing the most.
+    ///
+    /// Weights decrease linearly with age, the window being clamped to the
+    /// buffered intervals. When synced, intervals spanning a gap are left out.
+    /// This is a lighter alternative to `rpm_filtered`.
+    pub fn rpm_windowed(&self, window: usize) -> Option<f64> {
+        let teeth = || self.tooth_intervals().filter(|(edge, _)| edge.span == 1);
+
+        let available = teeth().count();
+        let window = window.min(available);
+        if window == 0 {
+            return None;
+        }
+
+        let (mut total, mut weights) = (0.0, 0.0);
+        for (weight, (_, interval)) in teeth().skip(available - window).enumerate() {
+            let weight = (weight + 1) as f64;
+            total += weight * self.seconds(interval);
+            weights += weight;
+        }
+        if total <= 0.0 {
+            return None;
+        }
+
+        Some(self.degrees_per_tooth() / (total / weights) / 6.0)
+    }
+
+    /// Engine speed estimated by the Kalman filter.
+    pub fn rpm_filtered(&self) -> Option<f64> {
+        self.estimate
+            .as_ref()
+            .map(|estimate| estimate.state()[1].to_degrees() / 6.0)
+    }
+
+    /// Torque accelerating the crank, in N·m, given the inertia of the
+    /// rotating assembly in kg·m².
+    ///
+    /// This is a proxy for comparing combustion events rather than a
+    /// calibrated figure: friction, pumping and load torques are not
+    /// accounted for, and the inertia is rarely known precisely.
+    pub fn torque_proxy(&self, inertia_kg_m2: f64) -> Option<f64> {
+        if self.sync != SyncState::Synced {
+            return None;
+        }
+
+        let estimate = self.estimate.as_ref()?;
+        // The acceleration is only observed through the velocity, a seed
+        // still holding its initial uncertainty hasn't been updated yet.
+        if estimate.covariance()[(2, 2)] >= SEED_COVARIANCE[(2, 2)] {
+            return None;
+        }
+
+        Some(inertia_kg_m2 * estimate.state()[2])
+    }
+
+    /// Velocity dip of the weakest cylinder over the last cycle, as a fraction
+    /// of the average velocity of all cylinders.
+    ///
+    /// Needs the cam phase to attribute segments to cylinders, and `None` is
+    /// returned below [`MISFIRE_MIN_RPM`].
+    pub fn misfire_index(&self) -> Option<f32> {
+        self.weakest_cylinder().map(|(_, deviation)| deviation)
+    }
+
+    /// Position in the firing order of the cylinder `misfire_index` is about.
+    pub fn misfire_cylinder(&self) -> Option<u8> {
+        self.weakest_cylinder().map(|(cylinder, _)| cylinder)
+    }
+
+    /// Position of a tooth whose edge comes consistently early or late, as
+    /// from a chipped or bent tooth.
+    ///
+    /// The interval deviations at each position are averaged over the last
+    /// 8 revolutions or so, which random jitter and electrical noise don't
+    /// survive while a wheel defect shows at the same position every time.
+    /// Needs sync and 8 revolutions since, and an edge off by at least 5% of
+    /// a tooth period.
+    pub fn suspect_tooth(&self) -> Option<u16> {
+        self.deviations.suspect(&self.pattern)
+    }
+
+    fn weakest_cylinder(&self) -> Option<(u8, f32)> {
+        self.phase()?;
+        if self.rpm()? < MISFIRE_MIN_RPM {
+            return None;
+        }
+        self.segments.weakest(&self.schedule)
+    }
+
+    /// Rate of change of the engine speed in RPM per second, comparing the
+    /// last two revolutions.
+    ///
+    /// Any run of as many intervals as there are teeth covers exactly one
+    /// revolution, so this doesn't need sync, only two revolutions of history.
+    pub fn rpm_rate(&self) -> Option<f64> {
+        let teeth = self.pattern.teeth_present() as usize;
+        let intervals = self.ticks.len().checked_sub(1)?;
+        let skip = intervals.checked_sub(2 * teeth)?;
+
+        let (mut previous, mut last) = (0, 0);
+        for (i, interval) in self.timer_intervals().skip(skip).enumerate() {
+            match i < teeth {
+                true => previous += interval,
+                false => last += interval,
+            }
+        }
+        if previous == 0 || last == 0 {
+            return None;
+        }
+
+        let previous = self.seconds(previous);
+        let last = self.seconds(last);
+
+        // Revolution averages are half a revolution away from each other on both sides.
+        Some((60.0 / last - 60.0 / previous) / ((previous + last) * 0.5))
+    }
+
+    /// Whether the engine is stalled, cranking or running at `now`, the
+    /// speed over the last interval being compared with `cranking_max_rpm`.
+    ///
+    /// The engine is stalled until two teeth measured a speed, and again once
+    /// no tooth came for the stall timeout, `with_stall_timeout`. The speed is
+    /// the one of `rpm`, so an unexpected gap may read as cranking while
+    /// searching. The history still holds the teeth from before a stall,
+    /// calling `reset` then keeps them from skewing the restart.
+    pub fn engine_state(&self, now: Instant, cranking_max_rpm: f64) -> EngineState {
+        let Some(last) = self.last_tick() else {
+            return EngineState::Stalled;
+        };
+
+        let idle = self.timer_ticks(now.as_micros()).saturating_sub(last);
+        if idle >= self.timer_ticks(self.stall_timeout.as_micros()) {
+            return EngineState::Stalled;
+        }
+
+        match self.rpm() {
+            None => EngineState::Stalled,
+            Some(rpm) if rpm < cranking_max_rpm => EngineState::Cranking,
+            Some(_) => EngineState::Running,
+        }
+    }
+
+    /// Whether the engine turns at `now` without having caught yet, see
+    /// `engine_state`.
+    pub fn is_cranking(&self, now: Instant, cranking_max_rpm: f64) -> bool {
+        self.engine_state(now, cranking_max_rpm) == EngineState::Cranking
+    }
+
+    /// Standard deviation of the crank angle extrapolated at `now` from the
+    /// last tooth, in degrees.
+    ///
+    /// The extrapolation follows the Kalman estimate of the angle at the last
+    /// tooth, the velocity and the acceleration, so its uncertainty is the one
+    /// of `θ + v·t + a·t²/2` from their covariance, plus the process noise
+    /// building up over the time `t` since the tooth. It grows with `t` until
+    /// the next tooth anchors the angle again.
+    pub fn angle_uncertainty_deg(&self, now: Instant) -> Option<f64> {
+        self.crank_angle_degrees()?;
+        let estimate = self.estimate.as_ref()?;
+        let t = self.since_last_tooth(now)?;
+
+        // The covariance predicted over `t`, as the next tooth would.
+        let motion = CrankMotion::new(t);
+        let predicted = motion.f * estimate.covariance() * motion.ft + motion.q;
+        Some(predicted[(0, 0)].max(0.0).sqrt().to_degrees())
+    }
+
+    /// Time from `now` until the crank reaches `target_deg`.
+    ///
+    /// The next crossing is returned when the target is behind the angle at
+    /// `now`, assuming the crank keeps the velocity of the last interval.
+    /// `None` once no tooth came for the stall timeout.
+    pub fn time_to_angle(&self, now: Instant, target_deg: f64) -> Option<Duration> {
+        let angle = self.crank_angle_degrees()?;
+        let velocity = self.velocity.filter(|&v| v >= MIN_ANGULAR_VELOCITY)?;
+        let elapsed = self.since_last_tooth(now)?;
+        if elapsed >= self.stall_timeout.as_micros() as f64 / 1_000_000.0 {
+            return None;
+        }
+
+        let cycle = self.cycle_degrees() / velocity;
+        let mut seconds = ((target_deg - angle) / velocity - elapsed) % cycle;
+        if seconds < 0.0 {
+            seconds += cycle;
+        }
+
+        Some(Duration::from_micros((seconds * 1_000_000.0) as u64))
+    }
+
+    /// Instants to open and close the injector of `cyl` for `pulse`, so that
+    /// injection ends `end_angle_deg` before the TDC of the cylinder.
+    ///
+    /// The close instant is the next crossing of the end angle after `now`, as
+    /// found by `time_to_angle`, and the injector opens `pulse` earlier. When
+    /// that opening instant has already passed, the injector opens at `now`
+    /// and closes late by the time missed, rather than cutting the pulse
+    /// short. A pulse lasting a full cycle at the current speed can't fit.
+    pub fn injection_window(
+        &self,
+        now: Instant,
+        cyl: usize,
+        end_angle_deg: f64,
+        pulse: Duration,
+    ) -> Result<(Instant, Instant), InjectionError> {
+        if cyl >= self.schedule.cylinders() as usize {
+            return Err(InjectionError::UnknownCylinder);
+        }
+
+        let end = self.tdc_degrees(cyl as u8) - end_angle_deg;
+        let until_close = self
+            .time_to_angle(now, end)
+            .ok_or(InjectionError::NotSynced)?;
+        let velocity = self.velocity.ok_or(InjectionError::NotSynced)?;
+        let seconds = self.cycle_degrees() / velocity;
+        if pulse.as_micros() as f64 >= seconds * 1_000_000.0 {
+            return Err(InjectionError::PulseTooLong);
+        }
+
+        match until_close.checked_sub(pulse) {
+            Some(until_open) => Ok((now + until_open, now + until_close)),
+            None => Ok((now, now + pulse)),
+        }
+    }
+
+    /// TDC angle of `cylinder` within the decoding cycle, the one of the
+    /// firing schedule shifted by the TDC offset of the pattern.
+    pub fn tdc_degrees(&self, cylinder: u8) -> f64 {
+        let angle = self.schedule.tdc_degrees(cylinder, self.mode);
+        (angle + self.pattern.tdc_offset_degrees()) % self.cycle_degrees()
+    }
+
+    /// Instant the next tooth is expected at, assuming the crank keeps the
+    /// velocity of the last interval.
+    ///
+    /// When a gap comes next, the wait covers its missing teeth as well.
+    pub fn next_tooth_at(&self) -> Option<Instant> {
+        self.crank_angle_degrees()?;
+        let velocity = self.velocity.filter(|&v| v >= MIN_ANGULAR_VELOCITY)?;
+
+        let seconds = self.degrees_to_next_tooth() / velocity;
+        let wait = (seconds * self.timer_freq.0 as f64) as u64;
+        Some(self.instant(self.last_tick()? + wait))
+    }
+
+    /// Crank angle at `now`, extrapolated from the last tooth at the velocity
+    /// of the last interval, in degrees.
+    ///
+    /// The extrapolation stops at the next expected tooth, which lies past the
+    /// missing teeth when a gap comes next. A late tooth thus holds the angle
+    /// instead of letting it run ahead of the wheel.
+    pub fn angle_at(&self, now: Instant) -> Option<f64> {
+        let angle = self.crank_angle_degrees()?;
+        let velocity = self.velocity.filter(|&v| v >= MIN_ANGULAR_VELOCITY)?;
+        let elapsed = self.since_last_tooth(now)?;
+
+        let travel = (velocity * elapsed).min(self.degrees_to_next_tooth());
+        Some((angle + travel) % self.cycle_degrees())
+    }
+
+    /// Crank angle at `now`, integrated from the last tooth with the Kalman
+    /// estimate of the velocity and the acceleration, in degrees.
+    ///
+    /// Unlike `angle_at`, which keeps the velocity of the last interval, this
+    /// follows `θ + ω·t + α·t²/2` and stays closer to the wheel between teeth
+    /// while the speed changes. Each tooth anchors the angle at its own
+    /// position again, so the prediction error doesn't build up across teeth.
+    /// The extrapolation stops at the next expected tooth as well.
+    pub fn fine_angle(&self, now: Instant) -> Option<f64> {
+        let angle = self.crank_angle_degrees()?;
+        let state = self.estimate.as_ref()?.state();
+        if state[1].to_degrees() < MIN_ANGULAR_VELOCITY {
+            return None;
+        }
+        let t = self.since_last_tooth(now)?;
+
+        let travel = taylor_terms(t).dot(state).to_degrees();
+        let travel = travel.clamp(0.0, self.degrees_to_next_tooth());
+        Some((angle + travel) % self.cycle_degrees())
+    }
+
+    // Angle from the last tooth to the next present one.
+    fn degrees_to_next_tooth(&self) -> f64 {
+        let next = self.pattern.next_tooth(self.tooth);
+        let positions = match self.distance(self.tooth, next) {
+            0 => self.pattern.teeth_per_rev(),
+            distance => distance,
+        };
+        positions as f64 * self.degrees_per_tooth()
+    }
+
+    // Seconds from the last tooth to `now`, `None` before the first one and
+    // for an instant before it.
+    fn since_last_tooth(&self, now: Instant) -> Option<f64> {
+        let now = self.timer_ticks(now.as_micros());
+        Some(self.seconds(now.checked_sub(self.last_tick()?)?))
+    }
+
+    // Seconds lasted by `ticks` periods of the capture timer.
+    fn seconds(&self, ticks: u64) -> f64 {
+        ticks as f64 / self.timer_freq.0 as f64
+    }
+
+    // Microseconds lasted by `ticks` periods of the capture timer.
+    fn micros(&self, ticks: u64) -> u64 {
+        ticks * 1_000_000 / self.timer_freq.0 as u64
+    }
+
+    // `ticks` periods of the capture timer, down to the resolution of the
+    // embassy time base.
+    fn duration(&self, ticks: u64) -> Duration {
+        Duration::from_micros(self.micros(ticks))
+    }
+
+    // Instant of the capture timer value `tick`, down to the resolution of
+    // the embassy time base.
+    fn instant(&self, tick: u64) -> Instant {
+        Instant::from_micros(self.micros(tick))
+    }
+
+    // Capture timer periods in `micros`.
+    fn timer_ticks(&self, micros: u64) -> u64 {
+        micros * self.timer_freq.0 as u64 / 1_000_000
+    }
+
+    // Angle covered by one full decoding cycle.
+    fn cycle_degrees(&self) -> f64 {
+        match self.mode {
+            DecodeMode::CrankOnly => 360.0,
+            DecodeMode::Sequential => 720.0,
+        }
+    }
+
+    /// Drops all decoding state so the wheel behaves as freshly constructed,
+    /// while keeping its configuration.
+    pub fn reset(&mut self) {
+        self.ticks.clear();
+        self.sync = SyncState::Searching;
+        self.tooth = 0;
+        self.teeth_since_gap = 0;
+        self.last_gap = None;
+        self.revolution = None;
+        self.cam_seen = false;
+        self.segments.invalidate();
+        self.deviations.invalidate();
+        self.last_was_gap = false;
+        self.dropouts = 0;
+        self.last_dropout = None;
+        self.implausible_ticks = 0;
+        self.velocity = None;
+        self.estimate = None;
+        self.skipped_updates = 0;
+    }
+}
+
+/// Reason for a [`TriggerWheelBuilder`] to refuse its configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(Format))]
+pub enum BuildError {
+    /// The pattern has no tooth positions, there is no angle to decode.
+    NoTeeth,
+    /// The tick history can't hold a full revolution of the pattern.
+    HistoryTooShort,
+    /// The debounce is at least as long as a tooth period at the redline, it
+    /// would swallow genuine teeth.
+    DebounceTooLong,
+    /// The gap ratio is at least the gap period multiplier of the pattern,
+    /// the reference gap would never be found.
+    GapRatioUnreachable,
+    /// The gap hysteresis ratio is above the gap ratio, an expected gap would
+    /// take more to keep the sync than to find it.
+    HysteresisAboveRatio,
+}
+
+/// Collects the configuration of a [`TriggerWheel`] and checks it as a whole.
+///
+/// `TriggerWheel::new` stays the zero configuration path, the `with_*`
+/// methods of the wheel apply each setting without any cross checks.
+pub struct TriggerWheelBuilder<const N: usize, P = SixtyMinusTwo> {
+    pattern: P,
+    mode: DecodeMode,
+    schedule: FiringSchedule,
+    timer_freq: Hertz,
+    timer_bits: u8,
+    gap_ratio: f64,
+    // The gap ratio unless set.
+    gap_exit_ratio: Option<f64>,
+    debounce: Duration,
+    stall_timeout: Duration,
+    max_rpm_step: Option<f64>,
+    gap_signal: Option<&'static GapSignal>,
+    redline_rpm: Option<f64>,
+}
+
+impl<const N: usize, P: TriggerPattern + Default> TriggerWheelBuilder<N, P> {
+    pub fn new() -> Self {
+        Self {
+            pattern: P::default(),
+            mode: DecodeMode::default(),
+            schedule: FiringSchedule::default(),
+            timer_freq: DEFAULT_TIMER_FREQ,
+            timer_bits: DEFAULT_TIMER_BITS,
+            gap_ratio: DEFAULT_GAP_RATIO,
+            gap_exit_ratio: None,
+            debounce: Duration::from_ticks(0),
+            stall_timeout: DEFAULT_STALL_TIMEOUT,
+            max_rpm_step: None,
+            gap_signal: None,
+            redline_rpm: None,
+        }
+    }
+}
+
+impl<const N: usize, P: TriggerPattern + Default> Default for TriggerWheelBuilder<N, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, P: TriggerPattern> TriggerWheelBuilder<N, P> {
+    pub fn pattern<Q: TriggerPattern>(self, pattern: Q) -> TriggerWheelBuilder<N, Q> {
+        TriggerWheelBuilder {
+            pattern,
+            mode: self.mode,
+            schedule: self.schedule,
+            timer_freq: self.timer_freq,
+            timer_bits: self.timer_bits,
+            gap_ratio: self.gap_ratio,
+            gap_exit_ratio: self.gap_exit_ratio,
+            debounce: self.debounce,
+            stall_timeout: self.stall_timeout,
+            max_rpm_step: self.max_rpm_step,
+            gap_signal: self.gap_signal,
+            redline_rpm: self.redline_rpm,
+        }
+    }
+
+    pub fn decode_mode(mut self, mode: DecodeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn firing_schedule(mut self, schedule: FiringSchedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    pub fn timer_freq(mut self, freq: Hertz) -> Self {
+        self.timer_freq = freq;
+        self
+    }
+
+    pub fn timer_bits(mut self, bits: u8) -> Self {
+        self.timer_bits = bits;
+        self
+    }
+
+    pub fn gap_ratio(mut self, gap_ratio: f64) -> Self {
+        self.gap_ratio = gap_ratio;
+        self
+    }
+
+    /// Ratio down to which expected gaps are accepted while synced, see
+    /// [`TriggerWheel::with_gap_hysteresis`]. The gap ratio without it,
+    /// whichever order both are set in.
+    pub fn gap_hysteresis(mut self, exit_ratio: f64) -> Self {
+        self.gap_exit_ratio = Some(exit_ratio);
+        self
+    }
+
+    pub fn debounce(mut self, min: Duration) -> Self {
+        self.debounce = min;
+        self
+    }
+
+    pub fn stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = timeout;
+        self
+    }
+
+    pub fn max_rpm_step(mut self, fraction: f64) -> Self {
+        self.max_rpm_step = Some(fraction);
+        self
+    }
+
+    pub fn gap_signal(mut self, signal: &'static GapSignal) -> Self {
+        self.gap_signal = Some(signal);
+        self
+    }
+
+    /// Highest engine speed the wheel has to decode, only used to check the
+    /// debounce against the shortest tooth period.
+    pub fn redline_rpm(mut self, rpm: f64) -> Self {
+        self.redline_rpm = Some(rpm);
+        self
+    }
+
+    pub fn build(self) -> Result<TriggerWheel<N, P>, BuildError> {
+        if self.pattern.teeth_per_rev() == 0 {
+            return Err(BuildError::NoTeeth);
+        }
+
+        if N <= self.pattern.teeth_per_rev() as usize {
+            return Err(BuildError::HistoryTooShort);
+        }
+
+        if let Some(rpm) = self.redline_rpm {
+            if self.debounce >= tooth_period(rpm, self.pattern.teeth_per_rev()) {
+                return Err(BuildError::DebounceTooLong);
+            }
+        }
+
+        if !self.pattern.gaps().is_empty() && self.gap_ratio >= self.pattern.gap_period_multiplier()
+        {
+            return Err(BuildError::GapRatioUnreachable);
+        }
+
+        let gap_exit_ratio = self.gap_exit_ratio.unwrap_or(self.gap_ratio);
+        if gap_exit_ratio > self.gap_ratio {
+            return Err(BuildError::HysteresisAboveRatio);
+        }
+
+        let mut wheel = TriggerWheel::with_pattern(self.pattern)
+            .with_decode_mode(self.mode)
+            .with_firing_schedule(self.schedule)
+            .with_timer_freq(self.timer_freq)
+            .with_timer_bits(self.timer_bits)
+            .with_gap_ratio(self.gap_ratio)
+            .with_gap_hysteresis(gap_exit_ratio)
+            .with_debounce(self.debounce)
+            .with_stall_timeout(self.stall_timeout);
+
+        if let Some(fraction) = self.max_rpm_step {
+            wheel = wheel.with_max_rpm_step(fraction);
+        }
+        if let Some(signal) = self.gap_signal {
+            wheel = wheel.with_gap_signal(signal);
+        }
+
+        Ok(wheel)
+    }
 }
 
-impl<const N: usize> ObservationModel<f64, U3, U1> for TriggerWheel<N> {
-    fn H(&self) -> &Matrix1x3<f64> {
-        static H: Matrix1x3<f64> = Matrix1x3::new(0.0, 1.0, 0.0);
-        &H
+// Arrival of a tooth, the measurement being the angle the crank turned by
+// since the last one. A timing error shows as an angle error growing with
+// the angular velocity, the capture timer resolution being uniformly
+// distributed over a tick.
+struct ToothArrival {
+    h: OMatrix<f64, U1, KalmanState>,
+    ht: OMatrix<f64, KalmanState, U1>,
+    r: Matrix1<f64>,
+}
+
+impl ToothArrival {
+    fn new(velocity: f64, timer_freq: Hertz) -> Self {
+        let mut h = OMatrix::<f64, U1, KalmanState>::zeros();
+        h[0] = 1.0;
+
+        let freq = timer_freq.0 as f64;
+        let timing_noise = 1.0 / (freq * freq * 12.0);
+
+        Self {
+            ht: h.transpose(),
+            h,
+            r: Matrix1::new(MEASUREMENT_NOISE + velocity * velocity * timing_noise),
+        }
     }
+}
 
-    fn HT(&self) -> &Matrix3x1<f64> {
-        static HT: Matrix3x1<f64> = Matrix3x1::new(0.0, 1.0, 0.0);
-        &HT
+impl ObservationModel<f64, KalmanState, U1> for ToothArrival {
+    fn H(&self) -> &OMatrix<f64, U1, KalmanState> {
+        &self.h
+    }
+
+    fn HT(&self) -> &OMatrix<f64, KalmanState, U1> {
+        &self.ht
     }
 
     fn R(&self) -> &Matrix1<f64> {
-        static R: Matrix1<f64> = Matrix1::new(10.0);
-        &R
+        &self.r
     }
 }
 
-impl<const N: usize> TransitionModelLinearNoControl<f64, U3> for TriggerWheel<N> {
-    fn F(&self) -> &Matrix3<f64> {
-        // State transition matrix for constant acceleration model.
-        #[rustfmt::skip]
-        static F: Matrix3<f64> = Matrix3::new(
-            1.0,  DT, DT_SQUARED_HALF, // [1   dt  dt * dt / 2 ]
-            0.0, 1.0, DT,              // [0   1   dt          ]
-            0.0, 0.0, 1.0,             // [0   0   1           ]
-        );
-        &F
-    }
-
-    fn FT(&self) -> &Matrix3<f64> {
-        // Transpose of the state transition matrix.
-        // Used in the Kalman filter equations for covariance propagation:
-        // P' = F·P·Fᵀ + Q
-        #[rustfmt::skip]
-        static FT: Matrix3<f64> = Matrix3::new(
-            1.0,            0.0, 0.0, // [1            0   0]
-            DT,             1.0, 0.0, // [dt           1   0]
-            DT_SQUARED_HALF, DT, 1.0, // [dt * dt / 2  dt  1]
-        );
-        &FT
+// Motion of the crank over the interval between two teeth, with a constant
+// acceleration (or jerk) and white noise driving it.
+struct CrankMotion {
+    f: OMatrix<f64, KalmanState, KalmanState>,
+    ft: OMatrix<f64, KalmanState, KalmanState>,
+    q: OMatrix<f64, KalmanState, KalmanState>,
+}
+
+impl CrankMotion {
+    fn new(dt: f64) -> Self {
+        let mut f = OMatrix::<f64, KalmanState, KalmanState>::zeros();
+        let mut q = OMatrix::<f64, KalmanState, KalmanState>::zeros();
+        let n = f.nrows();
+
+        // Each state integrates the following ones over dt:
+        // F[i][j] = dt^(j - i) / (j - i)!
+        for i in 0..n {
+            let mut term = 1.0;
+            for j in i..n {
+                if j > i {
+                    term *= dt / (j - i) as f64;
+                }
+                f[(i, j)] = term;
+            }
+        }
+
+        // Noise on the highest derivative integrated down to each state, for
+        // p and r derivatives short of the highest:
+        // Q[i][j] = q · dt^(p + r + 1) / ((p + r + 1) · p! · r!)
+        for i in 0..n {
+            for j in 0..n {
+                let (p, r) = (n - 1 - i, n - 1 - j);
+                let order = (p + r + 1) as i32;
+                q[(i, j)] =
+                    PROCESS_NOISE * dt.powi(order) / (order as f64 * factorial(p) * factorial(r));
+            }
+        }
+
+        Self {
+            ft: f.transpose(),
+            f,
+            q,
+        }
     }
+}
 
-    fn Q(&self) -> &Matrix3<f64> {
-        // Process noise covariance matrix.
-        // Diagonal elements represent uncertainty in the model for each state variable:
-        // - Q[0,0] = 0.001: Low noise for angle (position)      - most  predictable
-        // - Q[1,1] =  0.01: Medium noise for angular velocity   - less  predictable
-        // - Q[2,2] =   0.1: High noise for angular acceleration - least predictable
-        //
-        // Higher values make the filter more responsive to measurements but noisier.
-        // Lower values make the filter smoother but slower to respond to changes.
-        // The increasing values (0.001 → 0.01 → 0.1) reflect decreasing confidence
-        // in the model as we move from position to velocity to acceleration.
-        #[rustfmt::skip]
-        static Q: Matrix3<f64> = Matrix3::new(
-            0.001,  0.0, 0.0,
-              0.0, 0.01, 0.0,
-              0.0,  0.0, 0.1,
-        );
-        &Q
+impl TransitionModelLinearNoControl<f64, KalmanState> for CrankMotion {
+    fn F(&self) -> &OMatrix<f64, KalmanState, KalmanState> {
+        &self.f
+    }
+
+    fn FT(&self) -> &OMatrix<f64, KalmanState, KalmanState> {
+        &self.ft
+    }
+
+    fn Q(&self) -> &OMatrix<f64, KalmanState, KalmanState> {
+        &self.q
     }
 }
 
+fn factorial(n: usize) -> f64 {
+    (1..=n).map(|k| k as f64).product()
+}
+
+// Coefficients `t^i / i!` of each state in the angle extrapolated over `t`
+// seconds.
+fn taylor_terms(t: f64) -> OVector<f64, KalmanState> {
+    let mut terms = OVector::<f64, KalmanState>::zeros();
+    let mut term = 1.0;
+    for i in 0..terms.len() {
+        if i > 0 {
+            term *= t / i as f64;
+        }
+        terms[i] = term;
+    }
+    terms
+}
+
+// Timer periods between two values of the history, which never wraps.
+fn interval_between(earlier: u64, later: u64) -> u64 {
+    later - earlier
+}
+
+// Largest value of a timer counter `bits` wide, up to 32.
+fn timer_mask(bits: u8) -> u64 {
+    u64::MAX >> (64 - bits.clamp(1, 32))
+}
+
+// Median of up to 16 `intervals`, an even count averaging the two middle ones.
+fn median(intervals: impl Iterator<Item = u64>) -> Option<u64> {
+    let mut sorted = [0; MAX_MEDIAN_WINDOW];
+    let mut len = 0;
+
+    // Insertion sort, the window being tiny.
+    for interval in intervals.take(MAX_MEDIAN_WINDOW) {
+        let mut i = len;
+        while i > 0 && sorted[i - 1] > interval {
+            sorted[i] = sorted[i - 1];
+            i -= 1;
+        }
+        sorted[i] = interval;
+        len += 1;
+    }
+
+    match len {
+        0 => None,
+        _ if len % 2 == 1 => Some(sorted[len / 2]),
+        _ => Some((sorted[len / 2 - 1] + sorted[len / 2]) / 2),
+    }
+}
+
+fn is_gap(interval: u64, baseline: u64, ratio: f64) -> bool {
+    interval as f64 >= baseline as f64 * ratio
+}
+
 #[cfg(feature = "defmt")]
-impl<const N: usize> Format for TriggerWheel<N> {
+impl<const N: usize, P: TriggerPattern> Format for TriggerWheel<N, P> {
     fn format(&self, f: defmt::Formatter) {
-        defmt::write!(f, "TriggerWheel {{ ticks_count: {} }}", self.ticks_count())
+        defmt::write!(
+            f,
+            "TriggerWheel {{ ticks_count: {}, sync: {} }}",
+            self.ticks_count(),
+            self.sync
+        )
     }
 }
+
+#[cfg(test)]
+mod tests;
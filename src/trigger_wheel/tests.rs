@@ -0,0 +1,1262 @@
+use super::*;
+use crate::tick_generator::TickGenerator;
+use crate::trigger_pattern::{EvenTeeth, Gap, GmThreeX, ToneRing};
+
+// Feeds the next `teeth` ticks of `generator` to `wheel`.
+fn run<const N: usize, P: TriggerPattern, Q: TriggerPattern>(
+    wheel: &mut TriggerWheel<N, P>,
+    generator: &mut TickGenerator<Q>,
+    teeth: usize,
+) {
+    for _ in 0..teeth {
+        let tick = generator.next_tick().unwrap();
+        let _ = wheel.add_tick(tick);
+    }
+}
+
+#[test]
+fn reset_clears_the_decoding_state() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new()
+        .with_decode_mode(DecodeMode::Sequential)
+        .with_gap_ratio(2.0);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    run(&mut wheel, &mut generator, 3 * 58);
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+    assert!(wheel.ticks_count() > 0);
+
+    wheel.reset();
+
+    assert_eq!(wheel.ticks_count(), 0);
+    assert_eq!(wheel.sync_state(), SyncState::Searching);
+    assert_eq!(wheel.crank_angle_degrees(), None);
+    assert_eq!(wheel.rpm(), None);
+    assert_eq!(wheel.rpm_filtered(), None);
+    assert_eq!(wheel.decode_mode(), DecodeMode::Sequential);
+    assert_eq!(wheel.gap_ratio, 2.0);
+
+    // Decodes again as freshly constructed.
+    run(&mut wheel, &mut generator, 58 + 1);
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+}
+
+#[test]
+fn debounce_keeps_one_tooth_of_a_bounce_burst() {
+    let mut wheel: TriggerWheel<120> =
+        TriggerWheel::new().with_debounce(Duration::from_micros(100));
+    for tick in [1000, 2000, 3000] {
+        wheel.add_tick(Tick::from_ticks(tick)).unwrap();
+    }
+
+    // The edge at 4000 µs bounces three times within 60 µs.
+    assert_eq!(
+        wheel.add_tick(Tick::from_ticks(4000)),
+        Ok(Some(Duration::from_micros(1000)))
+    );
+    for bounce in [4020, 4040, 4060] {
+        assert_eq!(wheel.add_tick(Tick::from_ticks(bounce)), Ok(None));
+    }
+    assert_eq!(wheel.ticks_count(), 4);
+
+    // The next tooth is measured from the accepted edge.
+    assert_eq!(
+        wheel.add_tick(Tick::from_ticks(5000)),
+        Ok(Some(Duration::from_micros(1000)))
+    );
+    assert_eq!(wheel.ticks_count(), 5);
+}
+
+#[test]
+fn no_debounce_by_default() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new();
+    wheel.add_tick(Tick::from_ticks(1000)).unwrap();
+    wheel.add_tick(Tick::from_ticks(1020)).unwrap();
+    assert_eq!(wheel.ticks_count(), 2);
+}
+
+#[test]
+fn time_to_angle_counts_from_now_to_the_next_crossing() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+    // A tooth every millisecond, 6° per millisecond.
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 1000.0);
+    let mut last = Instant::from_ticks(0);
+    for _ in 0..58 + 20 {
+        last = generator.next_instant().unwrap();
+        let _ = wheel.add_tick_at(last);
+    }
+    assert_eq!(wheel.crank_angle_degrees(), Some(114.0));
+
+    let now = last + Duration::from_micros(500);
+    // Within the rounding of the generated instants to 10 µs.
+    let micros = |target| wheel.time_to_angle(now, target).unwrap().as_micros();
+    assert!(micros(144.0).abs_diff(4_500) <= 5);
+    // Passed since the last tooth, reached again a revolution later.
+    assert!(micros(115.0).abs_diff(59_667) <= 5);
+    assert!(micros(90.0).abs_diff(55_500) <= 5);
+
+    // A stalled crank never gets there.
+    let stalled = last + DEFAULT_STALL_TIMEOUT;
+    assert_eq!(wheel.time_to_angle(stalled, 144.0), None);
+}
+
+#[test]
+fn injection_window_ends_at_the_angle_and_opens_the_pulse_earlier() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 1000.0);
+    let now = Instant::from_ticks(0);
+    assert_eq!(
+        wheel.injection_window(now, 0, 30.0, Duration::from_millis(2)),
+        Err(InjectionError::NotSynced)
+    );
+
+    let mut last = now;
+    for _ in 0..58 + 20 {
+        last = generator.next_instant().unwrap();
+        let _ = wheel.add_tick_at(last);
+    }
+    assert_eq!(wheel.crank_angle_degrees(), Some(114.0));
+
+    // Cylinder 1 ending 30° before its TDC at 180°, 36° ahead of the crank.
+    let now = last + Duration::from_micros(500);
+    let window = |pulse| wheel.injection_window(now, 1, 30.0, Duration::from_micros(pulse));
+    let (open, close) = window(2_000).unwrap();
+    assert!((close - now).as_micros().abs_diff(5_500) <= 5);
+    assert_eq!(close - open, Duration::from_micros(2_000));
+
+    // Longer than the time left, opening at once instead of a cycle later.
+    assert_eq!(window(8_000), Ok((now, now + Duration::from_micros(8_000))));
+    assert_eq!(window(60_000), Err(InjectionError::PulseTooLong));
+    assert_eq!(
+        wheel.injection_window(now, 4, 30.0, Duration::from_millis(2)),
+        Err(InjectionError::UnknownCylinder)
+    );
+    assert_eq!(
+        wheel.injection_window(
+            last + DEFAULT_STALL_TIMEOUT,
+            1,
+            30.0,
+            Duration::from_millis(2)
+        ),
+        Err(InjectionError::NotSynced)
+    );
+}
+
+#[test]
+fn the_gap_signal_carries_each_confirmed_gap() {
+    static GAPS: GapSignal = Signal::new();
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new()
+        .with_gap_ratio(2.0)
+        .with_gap_signal(&GAPS);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+
+    // The gap acquiring sync is not signaled.
+    for _ in 0..58 + 1 {
+        let _ = wheel.add_tick_at(generator.next_instant().unwrap());
+    }
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+    assert_eq!(GAPS.try_take(), None);
+
+    for _ in 0..2 * 58 {
+        let position = generator.position();
+        let instant = generator.next_instant().unwrap();
+        let _ = wheel.add_tick_at(instant);
+        match position {
+            // Tooth 0 ends the gap.
+            0 => assert_eq!(GAPS.try_take(), Some(instant)),
+            _ => assert!(!GAPS.signaled()),
+        }
+    }
+}
+
+#[test]
+fn the_angle_uncertainty_grows_with_the_time_since_the_tooth() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    let mut last = Instant::from_ticks(0);
+    for _ in 0..3 * 58 {
+        last = generator.next_instant().unwrap();
+        let _ = wheel.add_tick_at(last);
+    }
+
+    let mut previous = 0.0;
+    for millis in 0..10 {
+        let elapsed = Duration::from_millis(millis);
+        let uncertainty = wheel.angle_uncertainty_deg(last + elapsed).unwrap();
+        assert!(uncertainty > previous, "{uncertainty} at {millis} ms");
+        previous = uncertainty;
+
+        // The estimate extrapolated along with the noise driving it since.
+        let t = elapsed.as_micros() as f64 / 1_000_000.0;
+        let sensitivity = taylor_terms(t);
+        let covariance = wheel.estimate.as_ref().unwrap().covariance();
+        let extrapolated = (sensitivity.transpose() * covariance * sensitivity)[0];
+        let variance = extrapolated + CrankMotion::new(t).q[(0, 0)];
+        let expected = variance.sqrt().to_degrees();
+        assert!(
+            (uncertainty - expected).abs() < expected * 1e-9,
+            "{uncertainty} != {expected}"
+        );
+    }
+}
+
+#[test]
+fn force_sync_sets_the_angle_of_the_last_tooth() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    run(&mut wheel, &mut generator, 20);
+    assert_eq!(wheel.sync_state(), SyncState::Searching);
+
+    assert_eq!(wheel.force_sync(60), Err(InvalidTooth));
+    assert_eq!(wheel.force_sync(58), Err(InvalidTooth));
+    assert_eq!(wheel.sync_state(), SyncState::Searching);
+
+    wheel.force_sync(19).unwrap();
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+    assert_eq!(wheel.crank_angle_degrees(), Some(114.0));
+
+    run(&mut wheel, &mut generator, 5);
+    assert_eq!(wheel.crank_angle_degrees(), Some(144.0));
+
+    // The gap comes where the forced position expects it.
+    run(&mut wheel, &mut generator, 34);
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+    assert_eq!(wheel.crank_angle_degrees(), Some(0.0));
+    assert!(wheel.last_was_gap());
+}
+
+#[test]
+fn a_first_interval_over_the_gap_rejects_no_tooth() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new()
+        .with_gap_ratio(2.0)
+        .with_max_rpm_step(0.5);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    // Starting on tooth 57, the first interval spans the gap.
+    for _ in 0..57 {
+        generator.next_tick();
+    }
+
+    let synced = (0..62).position(|_| {
+        let _ = wheel.add_tick(generator.next_tick().unwrap());
+        wheel.sync_state() == SyncState::Synced
+    });
+    assert_eq!(wheel.implausible_ticks(), 0);
+    assert!((wheel.rpm().unwrap() - 3000.0).abs() < 10.0);
+    // Synced on the second gap, a revolution after the first.
+    assert_eq!(synced, Some(59));
+}
+
+#[test]
+fn an_rpm_spike_is_rejected() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new()
+        .with_gap_ratio(2.0)
+        .with_max_rpm_step(0.5);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    run(&mut wheel, &mut generator, 10);
+    assert_eq!(wheel.sync_state(), SyncState::Searching);
+
+    // A spurious edge a third of a tooth in, 9000 RPM, while searching.
+    let last = wheel.latest_tick().unwrap().as_ticks();
+    let spike = Tick::from_ticks(last + 111);
+    assert_eq!(wheel.add_tick(spike), Err(TickError::Implausible));
+    run(&mut wheel, &mut generator, 60);
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+
+    // And while synced, the position carrying on over it.
+    let angle = wheel.crank_angle_degrees().unwrap();
+    let last = wheel.latest_tick().unwrap().as_ticks();
+    let spike = Tick::from_ticks(last + 111);
+    assert_eq!(wheel.add_tick(spike), Err(TickError::Implausible));
+    run(&mut wheel, &mut generator, 1);
+    assert_eq!(wheel.crank_angle_degrees(), Some(angle + 6.0));
+    assert_eq!(wheel.implausible_ticks(), 2);
+    assert!((wheel.rpm().unwrap() - 3000.0).abs() < 10.0);
+    assert_eq!(wheel.dropouts(), 0);
+}
+
+#[test]
+fn the_filtered_speed_follows_a_synthetic_profile() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 2000.0);
+    run(&mut wheel, &mut generator, 3 * 58);
+
+    // Steady, then a 4000 RPM/s ramp, then steady again.
+    let mut worst: f64 = 0.0;
+    for (rate, teeth) in [(0.0, 58), (4000.0, 4 * 58), (0.0, 2 * 58)] {
+        generator.set_rpm_rate(rate);
+        for tooth in 0..teeth {
+            let rpm = generator.rpm();
+            let _ = wheel.add_tick(generator.next_tick().unwrap());
+            // Each change of rate takes a revolution to be followed.
+            if tooth >= 58 {
+                let error = (wheel.rpm_filtered().unwrap() - rpm).abs() / rpm;
+                worst = worst.max(error);
+            }
+        }
+    }
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+    assert!(generator.rpm() > 2400.0, "{}", generator.rpm());
+    // The jerk model takes longer to settle on each change of rate.
+    assert!(worst < 0.01, "{worst}");
+}
+
+#[test]
+fn speed_is_known_before_sync_but_not_the_position() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    run(&mut wheel, &mut generator, 1);
+    assert_eq!(wheel.rpm(), None);
+    assert_eq!(wheel.interval_stats(), None);
+
+    run(&mut wheel, &mut generator, 20);
+    assert_eq!(wheel.sync_state(), SyncState::Searching);
+    let now = Instant::from_millis(10);
+
+    // Speed, from two teeth on.
+    assert!((wheel.rpm().unwrap() - 3000.0).abs() < 10.0);
+    let rev_per_sec = wheel.speed(SpeedUnit::RevPerSec).unwrap();
+    assert!((rev_per_sec - 50.0).abs() < 0.2);
+    assert!(wheel.rpm_filtered().is_some());
+    assert!(wheel.rpm_windowed(8).is_some());
+    assert!(wheel.median_interval(5).is_some());
+    assert!(wheel.interval_stats().is_some());
+
+    // Position, once synced.
+    assert_eq!(wheel.crank_angle_degrees(), None);
+    assert_eq!(wheel.phase(), None);
+    assert_eq!(wheel.ticks_since_gap(), None);
+    assert_eq!(wheel.time_since_gap(now), None);
+    assert_eq!(wheel.next_tooth_at(), None);
+    assert_eq!(wheel.angle_at(now), None);
+    assert_eq!(wheel.fine_angle(now), None);
+}
+
+#[test]
+fn the_gap_is_found_after_noisy_teeth() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+    // Teeth 1000 ticks apart, tooth 20 coming 400 early and tooth 57 600
+    // late, making the gap only 2.4 times the interval before it.
+    let ticks = (0..2).flat_map(|revolution| {
+        (0..58).map(move |position| {
+            let noise = match (revolution, position) {
+                (0, 20) => -400,
+                (0, 57) => 600,
+                _ => 0,
+            };
+            (revolution * 60 + position) * 1000 + noise
+        })
+    });
+
+    let mut synced = None;
+    for (index, tick) in ticks.enumerate() {
+        let _ = wheel.add_tick(Tick::from_ticks(tick as u32));
+        if synced.is_none() && wheel.sync_state() == SyncState::Synced {
+            synced = Some(index);
+            assert_eq!(wheel.crank_angle_degrees(), Some(0.0));
+        }
+    }
+    // Neither the interval after the early tooth nor the late one is a gap,
+    // and the gap is still one against the median.
+    assert_eq!(synced, Some(58));
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+    assert_eq!(wheel.median_interval(5), Some(Duration::from_micros(1000)));
+}
+
+#[test]
+fn reset_filter_reconverges_and_keeps_the_sync() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    run(&mut wheel, &mut generator, 3 * 58 + 10);
+
+    // Diverged, as after a transient.
+    wheel.seed_state(12000.0);
+    run(&mut wheel, &mut generator, 3);
+    let diverged = wheel.rpm_filtered().unwrap();
+    assert!((diverged - 3000.0).abs() > 300.0, "{diverged}");
+
+    let angle = wheel.crank_angle_degrees();
+    let ticks = wheel.ticks_count();
+    wheel.reset_filter();
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+    assert_eq!(wheel.crank_angle_degrees(), angle);
+    assert_eq!(wheel.ticks_count(), ticks);
+
+    for _ in 0..2 * 58 {
+        let _ = wheel.add_tick(generator.next_tick().unwrap());
+        assert_eq!(wheel.sync_state(), SyncState::Synced);
+    }
+    let filtered = wheel.rpm_filtered().unwrap();
+    assert!((filtered - 3000.0).abs() < 30.0, "{filtered}");
+}
+
+#[test]
+fn speed_units_agree_over_the_same_interval() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_timer_freq(mhz(1));
+    assert_eq!(wheel.speed(SpeedUnit::Rpm), None);
+    // 6° in 1 ms.
+    wheel.add_tick(Tick::from_ticks(0)).unwrap();
+    wheel.add_tick(Tick::from_ticks(1000)).unwrap();
+
+    let rpm = wheel.speed(SpeedUnit::Rpm).unwrap();
+    let rad_per_sec = wheel.speed(SpeedUnit::RadPerSec).unwrap();
+    let rev_per_sec = wheel.speed(SpeedUnit::RevPerSec).unwrap();
+    assert!((rpm - 1000.0).abs() < 1e-9, "{rpm}");
+    assert!((rev_per_sec - rpm / 60.0).abs() < 1e-9, "{rev_per_sec}");
+    assert!(
+        (rad_per_sec - rev_per_sec * core::f64::consts::TAU).abs() < 1e-9,
+        "{rad_per_sec}"
+    );
+    assert_eq!(wheel.rpm(), Some(rpm));
+}
+
+// Feeds `revolutions` of a 60-2 wheel with teeth 1000 ticks apart, every edge
+// off by up to `noise` ticks at random and the one of the tooth at `position`
+// by `chipped`.
+fn run_defective<const N: usize>(
+    wheel: &mut TriggerWheel<N>,
+    revolutions: core::ops::Range<u32>,
+    noise: u32,
+    (chipped_position, chipped): (u32, i32),
+) {
+    // Linear congruential generator, reproducible noise.
+    let mut seed: u32 = revolutions.start.wrapping_add(12345);
+    for revolution in revolutions {
+        for position in 0..58 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let jitter = ((seed >> 16) % (2 * noise + 1)) as i32 - noise as i32;
+            let offset = jitter
+                + if position == chipped_position {
+                    chipped
+                } else {
+                    0
+                };
+            let tick = ((revolution * 60 + position) * 1000 + 1000) as i32 + offset;
+            let _ = wheel.add_tick(Tick::from_ticks(tick as u32));
+        }
+    }
+}
+
+#[test]
+fn a_chipped_tooth_is_told_from_noise() {
+    let mut chipped: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+    run_defective(&mut chipped, 0..4, 40, (25, -100));
+    assert_eq!(chipped.sync_state(), SyncState::Synced);
+    // Not before enough revolutions are averaged.
+    assert_eq!(chipped.suspect_tooth(), None);
+    run_defective(&mut chipped, 4..16, 40, (25, -100));
+    assert_eq!(chipped.suspect_tooth(), Some(25));
+    assert_eq!(chipped.sync_state(), SyncState::Synced);
+
+    let mut noisy: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+    run_defective(&mut noisy, 0..16, 40, (25, 0));
+    assert_eq!(noisy.sync_state(), SyncState::Synced);
+    assert_eq!(noisy.suspect_tooth(), None);
+}
+
+#[test]
+fn fine_angle_meets_each_tooth_on_a_ramp() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 1500.0);
+    generator.set_rpm_rate(2000.0);
+    for _ in 0..3 * 58 {
+        let _ = wheel.add_tick_at(generator.next_instant().unwrap());
+    }
+
+    // Predicted at each tooth right before it is recorded, gaps included.
+    let (mut first, mut last): (f64, f64) = (0.0, 0.0);
+    for tooth in 0..3 * 58 {
+        let now = generator.next_instant().unwrap();
+        let predicted = wheel.fine_angle(now).unwrap();
+        wheel.add_tick_at(now).unwrap();
+        let actual = wheel.crank_angle_degrees().unwrap();
+
+        let error = ((predicted - actual + 540.0) % 360.0 - 180.0).abs();
+        match tooth < 58 {
+            true => first = first.max(error),
+            false => last = last.max(error),
+        }
+    }
+    // Within the 10 µs of the time base, and not building up.
+    assert!(first < 0.2, "{first}");
+    assert!(last < 0.2, "{last}");
+}
+
+#[test]
+fn contains_gap_once_the_buffer_spans_one() {
+    let mut wheel: TriggerWheel<64> = TriggerWheel::new().with_gap_ratio(2.0);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    run(&mut wheel, &mut generator, 40);
+    assert!(!wheel.contains_gap());
+
+    // Found while still searching, as soon as the gap is buffered.
+    run(&mut wheel, &mut generator, 18);
+    assert_eq!(wheel.sync_state(), SyncState::Searching);
+    assert!(!wheel.contains_gap());
+    run(&mut wheel, &mut generator, 1);
+    assert!(wheel.contains_gap());
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+
+    // A wheel without a gap never spans one, whatever the intervals.
+    let mut even: TriggerWheel<64, EvenTeeth<36>> = TriggerWheel::new().with_gap_ratio(2.0);
+    for tick in [0, 1000, 2000, 5000, 6000] {
+        even.add_tick(Tick::from_ticks(tick)).unwrap();
+    }
+    assert!(!even.contains_gap());
+}
+
+#[test]
+fn a_tone_ring_gives_the_speed_without_an_angle() {
+    let mut wheel: TriggerWheel<64, ToneRing<40>> = TriggerWheel::new();
+    let mut generator = TickGenerator::new(ToneRing::<40>::default(), 2000.0);
+    for _ in 0..3 * 40 {
+        run(&mut wheel, &mut generator, 1);
+        assert_eq!(wheel.crank_angle_degrees(), None);
+    }
+
+    let rpm = wheel.rpm().unwrap();
+    assert!((rpm - 2000.0).abs() < 1.0, "{rpm}");
+    assert_eq!(wheel.sync_state(), SyncState::Searching);
+}
+
+#[test]
+fn sixty_teeth_are_exactly_six_degrees_apart() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+    assert_eq!(wheel.degrees_per_tooth(), 6.0);
+    assert_eq!(SixtyMinusTwo::default().degrees_per_tooth(), 6.0);
+
+    // Every position of the revolution after sync, counted over the gap.
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    run(&mut wheel, &mut generator, 58);
+    for _ in 0..58 {
+        let position = generator.position();
+        let _ = wheel.add_tick(generator.next_tick().unwrap());
+        assert_eq!(wheel.crank_angle_degrees(), Some(6.0 * position as f64));
+    }
+    assert_eq!(wheel.crank_angle_degrees(), Some(342.0));
+
+    // A count not dividing 360 gives fractional angles.
+    let seven = TriggerWheelBuilder::<16>::new()
+        .pattern(RuntimeTeeth(7))
+        .build()
+        .unwrap();
+    assert_eq!(seven.degrees_per_tooth(), 360.0 / 7.0);
+}
+
+#[test]
+fn the_last_tooth_is_suspected_with_the_shortest_history() {
+    let mut wheel: TriggerWheel<61> = TriggerWheel::new().with_gap_ratio(2.0);
+    run_defective(&mut wheel, 0..16, 40, (57, 100));
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+    assert_eq!(wheel.suspect_tooth(), Some(57));
+}
+
+#[test]
+fn a_wrong_position_is_recovered_with_the_speed_step_limit() {
+    for (physical, forced) in [(9, 35), (21, 5)] {
+        let mut wheel: TriggerWheel<120> = TriggerWheel::new()
+            .with_gap_ratio(2.0)
+            .with_max_rpm_step(0.5);
+        let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+        run(&mut wheel, &mut generator, 58 + physical + 1);
+        wheel.force_sync(forced).unwrap();
+
+        // The tooth after the gap expected at the wrong place loses the sync,
+        // the real gap finds it again.
+        run(&mut wheel, &mut generator, 2 * 58);
+        let last = (generator.position() + 57) % 58;
+        assert_eq!(wheel.sync_state(), SyncState::Synced);
+        assert_eq!(wheel.crank_angle_degrees(), Some(6.0 * last as f64));
+
+        let (dropouts, implausible) = (wheel.dropouts(), wheel.implausible_ticks());
+        run(&mut wheel, &mut generator, 5 * 58);
+        assert_eq!(wheel.dropouts(), dropouts);
+        assert_eq!(wheel.implausible_ticks(), implausible);
+    }
+}
+
+#[test]
+fn intervals_keep_the_capture_timer_resolution() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_timer_freq(mhz(1));
+    // 333 µs, which the 10 µs embassy time base would read as 330.
+    for tick in [0, 333, 666] {
+        wheel.add_tick(Tick::from_ticks(tick)).unwrap();
+    }
+
+    let rpm = wheel.rpm().unwrap();
+    assert!((rpm - 6.0 / 333e-6 / 6.0).abs() < 1e-6, "{rpm}");
+    assert_eq!(wheel.latest_tick(), Some(Tick::from_ticks(666)));
+
+    let mut records = heapless::String::<64>::new();
+    wheel.write_records(&mut records).unwrap();
+    assert_eq!(records.as_str(), "333,333,3003.0\n666,333,3003.0\n");
+}
+
+#[test]
+fn records_account_for_the_gap_once_synced() {
+    let mut wheel: TriggerWheel<64> = TriggerWheel::new()
+        .with_timer_freq(mhz(1))
+        .with_gap_ratio(2.0);
+    let mut records = heapless::String::<1024>::new();
+    wheel.write_records(&mut records).unwrap();
+    assert_eq!(records.as_str(), "");
+
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    run(&mut wheel, &mut generator, 60);
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+
+    wheel.write_records(&mut records).unwrap();
+    assert_eq!(records.lines().count(), 59);
+    assert!(
+        records.ends_with("19000,334,2994.0\n20000,1000,3000.0\n20333,333,3003.0\n"),
+        "{records}"
+    );
+
+    // A full buffer is reported instead of cutting a record short.
+    let mut short = heapless::String::<24>::new();
+    assert!(wheel.write_records(&mut short).is_err());
+}
+
+// Teeth until the filtered speed settles within 1% of `rpm`.
+fn settling_teeth<const N: usize>(wheel: &mut TriggerWheel<N>, rpm: f64) -> usize {
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), rpm);
+    let mut settled = None;
+    for tooth in 0..1000 {
+        let _ = wheel.add_tick(generator.next_tick().unwrap());
+        let close = wheel
+            .rpm_filtered()
+            .is_some_and(|filtered| (filtered - rpm).abs() < rpm * 0.01);
+        match (close, settled) {
+            (true, None) => settled = Some(tooth),
+            (false, _) => settled = None,
+            _ => {}
+        }
+    }
+    settled.unwrap()
+}
+
+#[test]
+fn seeding_from_the_first_interval_converges_within_a_revolution() {
+    let mut seeded: TriggerWheel<120> = TriggerWheel::new();
+    let mut unseeded: TriggerWheel<120> = TriggerWheel::new();
+    // A filter starting from a standstill.
+    unseeded.seed_state(0.0);
+
+    let seeded = settling_teeth(&mut seeded, 3000.0);
+    let unseeded = settling_teeth(&mut unseeded, 3000.0);
+    assert!(seeded <= 2, "{seeded}");
+    assert!(unseeded >= 10 * seeded.max(1), "{unseeded}");
+}
+
+#[test]
+fn a_decimated_filter_syncs_and_tracks_a_ramp() {
+    for k in [1, 4, 8] {
+        let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+        wheel.set_kalman_decimation(k);
+        let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 1500.0);
+        generator.set_rpm_rate(2000.0);
+        run(&mut wheel, &mut generator, 58 + 1);
+        assert_eq!(wheel.sync_state(), SyncState::Synced, "k = {k}");
+
+        let mut worst: f64 = 0.0;
+        for _ in 0..3 * 58 {
+            run(&mut wheel, &mut generator, 1);
+            let error = wheel.rpm_filtered().unwrap() - generator.rpm();
+            worst = worst.max(error.abs());
+        }
+        // Lagging by a few teeth at most, 2000 rpm/s being about 1 rpm a tooth.
+        assert!(worst < 10.0, "k = {k}: {worst}");
+    }
+}
+
+#[test]
+fn rpm_rate_follows_a_ramp() {
+    for rpm_rate in [2000.0, -2000.0] {
+        let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+        let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+        generator.set_rpm_rate(rpm_rate);
+        // One interval short of two revolutions.
+        run(&mut wheel, &mut generator, 2 * 58);
+        assert_eq!(wheel.rpm_rate(), None);
+
+        run(&mut wheel, &mut generator, 58);
+        let rate = wheel.rpm_rate().unwrap();
+        assert!((rate - rpm_rate).abs() < 2000.0 * 0.05, "{rate}");
+    }
+}
+
+#[test]
+fn torque_proxy_follows_the_acceleration() {
+    for rpm_rate in [1500.0, -1500.0] {
+        let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+        let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+        generator.set_rpm_rate(rpm_rate);
+        run(&mut wheel, &mut generator, 50);
+        assert_eq!(wheel.torque_proxy(0.2), None);
+
+        run(&mut wheel, &mut generator, 3 * 58);
+        // 1500 RPM per second is 157 rad/s², 31.4 N·m for 0.2 kg·m².
+        let expected = 0.2 * (rpm_rate * 6.0).to_radians();
+        let torque = wheel.torque_proxy(0.2).unwrap();
+        assert!((torque - expected).abs() < expected.abs() * 0.1, "{torque}");
+    }
+}
+
+#[test]
+fn seed_state_sets_the_speed_before_any_interval() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new();
+    wheel.seed_state(250.0);
+    assert!((wheel.rpm_filtered().unwrap() - 250.0).abs() < 1e-9);
+    assert_eq!(wheel.rpm(), None);
+}
+
+#[test]
+fn a_duplicate_capture_leaves_the_decoder_untouched() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new();
+    assert_eq!(wheel.add_tick(Tick::from_ticks(1000)), Ok(None));
+    assert_eq!(
+        wheel.add_tick(Tick::from_ticks(1333)),
+        Ok(Some(Duration::from_micros(333)))
+    );
+    let rpm = wheel.rpm();
+
+    assert_eq!(
+        wheel.peek_interval(Tick::from_ticks(1333)),
+        Some(Duration::from_ticks(0))
+    );
+    assert_eq!(
+        wheel.add_tick(Tick::from_ticks(1333)),
+        Err(TickError::Duplicate)
+    );
+    assert_eq!(wheel.ticks_count(), 2);
+    assert_eq!(wheel.rpm(), rpm);
+
+    // The next edge is measured from the accepted one.
+    assert_eq!(
+        wheel.add_tick(Tick::from_ticks(1666)),
+        Ok(Some(Duration::from_micros(333)))
+    );
+}
+
+// Runs a synced 60-2 wheel at 3000 RPM on a `bits` wide counter across a
+// wrap of it.
+fn run_across_a_wrap(bits: u8) {
+    let mask = u32::MAX >> (32 - bits);
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new()
+        .with_gap_ratio(2.0)
+        .with_timer_bits(bits);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    // A revolution and a half before the wrap.
+    let start = mask - 30_000;
+    for _ in 0..3 * 58 {
+        let tick = generator.next_tick().unwrap().as_ticks();
+        let tick = Tick::from_ticks(tick.wrapping_add(start) & mask);
+        let interval = wheel.add_tick(tick).unwrap();
+        assert!(
+            interval.is_none_or(|i| i <= Duration::from_micros(1010)),
+            "{interval:?}"
+        );
+        assert_eq!(wheel.latest_tick(), Some(tick));
+    }
+    assert!(wheel.latest_tick().unwrap().as_ticks() < 60_000);
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+    assert!((wheel.rpm().unwrap() - 3000.0).abs() < 10.0);
+}
+
+#[test]
+fn intervals_are_measured_across_a_counter_wrap() {
+    run_across_a_wrap(32);
+    run_across_a_wrap(16);
+}
+
+#[test]
+fn an_out_of_order_tick_is_rejected() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new();
+    wheel.add_tick(Tick::from_ticks(5000)).unwrap();
+    assert_eq!(
+        wheel.add_tick(Tick::from_ticks(4000)),
+        Err(TickError::OutOfOrder)
+    );
+    assert_eq!(wheel.peek_interval(Tick::from_ticks(4000)), None);
+    assert_eq!(wheel.latest_tick(), Some(Tick::from_ticks(5000)));
+    assert_eq!(
+        wheel.add_tick(Tick::from_ticks(5333)),
+        Ok(Some(Duration::from_micros(333)))
+    );
+
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new();
+    wheel.add_tick_at(Instant::from_micros(5000)).unwrap();
+    assert_eq!(
+        wheel.add_tick_at(Instant::from_micros(4000)),
+        Err(TickError::OutOfOrder)
+    );
+    assert_eq!(wheel.ticks_count(), 1);
+}
+
+// Sync states of a 60-2 wheel over revolutions of 1000 tick teeth, the gap
+// of each revolution lasting the next of `gap_periods` tooth periods.
+fn sync_over_gaps<const N: usize>(
+    wheel: &mut TriggerWheel<N>,
+    gap_periods: impl IntoIterator<Item = f64>,
+) -> heapless::Vec<SyncState, 1024> {
+    let mut states = heapless::Vec::new();
+    let mut tick = 0.0;
+    for periods in gap_periods {
+        for tooth in 0..58 {
+            tick += match tooth {
+                0 => periods * 1000.0,
+                _ => 1000.0,
+            };
+            let _ = wheel.add_tick(Tick::from_ticks(tick as u32));
+            states.push(wheel.sync_state()).unwrap();
+        }
+    }
+    states
+}
+
+#[test]
+fn hysteresis_keeps_sync_on_gaps_hovering_at_the_ratio() {
+    let hovering = || (0..12).map(|i| if i % 2 == 1 { 2.95 } else { 2.85 });
+
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new()
+        .with_gap_ratio(2.9)
+        .with_gap_hysteresis(2.5);
+    let states = sync_over_gaps(&mut wheel, hovering());
+    let synced = states.iter().position(|&s| s == SyncState::Synced).unwrap();
+    // The first measured gap, at 2.95 periods, acquires the sync for good.
+    assert_eq!(synced, 58);
+    assert!(states[synced..].iter().all(|&s| s == SyncState::Synced));
+
+    // Without hysteresis, every short gap drops the sync.
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.9);
+    let states = sync_over_gaps(&mut wheel, hovering());
+    let losses = states
+        .windows(2)
+        .filter(|pair| pair == &[SyncState::Synced, SyncState::Searching])
+        .count();
+    assert_eq!(losses, 5);
+}
+
+// A wheel of evenly spaced teeth only known at run time.
+#[derive(Clone, Copy, Default)]
+struct RuntimeTeeth(u16);
+
+impl TriggerPattern for RuntimeTeeth {
+    fn teeth_per_rev(&self) -> u16 {
+        self.0
+    }
+
+    fn gaps(&self) -> &[Gap] {
+        &[]
+    }
+}
+
+#[test]
+fn build_rejects_inconsistent_configurations() {
+    let builder = || TriggerWheelBuilder::<120>::new();
+
+    assert!(matches!(
+        builder().pattern(RuntimeTeeth(0)).build(),
+        Err(BuildError::NoTeeth)
+    ));
+    assert!(matches!(
+        TriggerWheelBuilder::<60>::new()
+            .pattern(RuntimeTeeth(60))
+            .build(),
+        Err(BuildError::HistoryTooShort)
+    ));
+    // A tooth lasts 166 µs at 6000 RPM.
+    assert!(matches!(
+        builder()
+            .redline_rpm(6000.0)
+            .debounce(Duration::from_micros(200))
+            .build(),
+        Err(BuildError::DebounceTooLong)
+    ));
+    assert!(matches!(
+        builder().gap_ratio(3.0).build(),
+        Err(BuildError::GapRatioUnreachable)
+    ));
+    assert!(matches!(
+        builder().gap_ratio(1.8).gap_hysteresis(2.0).build(),
+        Err(BuildError::HysteresisAboveRatio)
+    ));
+
+    assert!(builder()
+        .redline_rpm(6000.0)
+        .debounce(Duration::from_micros(100))
+        .build()
+        .is_ok());
+}
+
+#[test]
+#[should_panic(expected = "The pattern must have tooth positions")]
+fn a_runtime_pattern_without_teeth_panics() {
+    let _: TriggerWheel<120, RuntimeTeeth> = TriggerWheel::with_pattern(RuntimeTeeth(0));
+}
+
+#[test]
+#[should_panic(expected = "The tick history must hold a full revolution of the pattern")]
+fn a_history_shorter_than_a_runtime_pattern_panics() {
+    let _: TriggerWheel<36, RuntimeTeeth> = TriggerWheel::with_pattern(RuntimeTeeth(36));
+}
+
+#[test]
+fn build_ignores_the_order_of_the_gap_setters() {
+    let ratio_first = TriggerWheelBuilder::<120>::new()
+        .gap_ratio(2.0)
+        .gap_hysteresis(1.6)
+        .build()
+        .unwrap();
+    let hysteresis_first = TriggerWheelBuilder::<120>::new()
+        .gap_hysteresis(1.6)
+        .gap_ratio(2.0)
+        .build()
+        .unwrap();
+
+    for wheel in [ratio_first, hysteresis_first] {
+        assert_eq!(wheel.gap_ratio, 2.0);
+        assert_eq!(wheel.gap_exit_ratio, 1.6);
+    }
+
+    let without = TriggerWheelBuilder::<120>::new()
+        .gap_ratio(2.0)
+        .build()
+        .unwrap();
+    assert_eq!(without.gap_exit_ratio, 2.0);
+}
+
+#[test]
+fn engine_state_boundaries() {
+    const CRANKING_MAX_RPM: f64 = 400.0;
+    let state = |wheel: &TriggerWheel<120>, micros| {
+        wheel.engine_state(Instant::from_micros(micros), CRANKING_MAX_RPM)
+    };
+
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new();
+    // Never turned, then a single tooth, without any speed.
+    assert_eq!(state(&wheel, 0), EngineState::Stalled);
+    let _ = wheel.add_tick(Tick::from_ticks(10_000));
+    assert_eq!(state(&wheel, 10_000), EngineState::Stalled);
+
+    // 6° in 2501 µs is just under 400 RPM, in 2499 µs just over.
+    let _ = wheel.add_tick(Tick::from_ticks(12_501));
+    assert_eq!(state(&wheel, 12_501), EngineState::Cranking);
+    assert!(wheel.is_cranking(Instant::from_micros(12_501), CRANKING_MAX_RPM));
+    let _ = wheel.add_tick(Tick::from_ticks(15_000));
+    assert_eq!(state(&wheel, 15_000), EngineState::Running);
+    assert!(!wheel.is_cranking(Instant::from_micros(15_000), CRANKING_MAX_RPM));
+
+    // Stalled from half a second without a tooth on.
+    assert_eq!(state(&wheel, 15_000 + 499_990), EngineState::Running);
+    assert_eq!(state(&wheel, 15_000 + 500_000), EngineState::Stalled);
+
+    // Turning again, from a fresh history.
+    wheel.reset();
+    let _ = wheel.add_tick(Tick::from_ticks(600_000));
+    let _ = wheel.add_tick(Tick::from_ticks(610_000));
+    assert_eq!(state(&wheel, 610_000), EngineState::Cranking);
+}
+
+#[test]
+fn the_stall_timeout_is_configurable() {
+    let mut wheel: TriggerWheel<120> =
+        TriggerWheel::new().with_stall_timeout(Duration::from_millis(100));
+    let _ = wheel.add_tick(Tick::from_ticks(0));
+    let _ = wheel.add_tick(Tick::from_ticks(10_000));
+
+    assert!(wheel.is_cranking(Instant::from_micros(109_990), 400.0));
+    assert_eq!(
+        wheel.engine_state(Instant::from_micros(110_000), 400.0),
+        EngineState::Stalled
+    );
+}
+
+#[test]
+fn rpm_windowed_at_a_steady_speed_leaves_the_gap_out() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new();
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    // Up to the tooth after the second gap.
+    run(&mut wheel, &mut generator, 2 * 58 + 1);
+    assert_eq!(wheel.ticks_since_gap(), Some(0));
+
+    for window in [1, 10, 58, 1000] {
+        let rpm = wheel.rpm_windowed(window).unwrap();
+        assert!((rpm - 3000.0).abs() < 10.0, "{window}: {rpm}");
+    }
+    assert_eq!(wheel.rpm_windowed(0), None);
+}
+
+#[test]
+fn rpm_windowed_weighs_the_recent_teeth_on_a_ramp() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new();
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 2000.0);
+    generator.set_rpm_rate(5000.0);
+    run(&mut wheel, &mut generator, 2 * 58 + 10);
+
+    const WINDOW: usize = 8;
+    let intervals = wheel.intervals().count();
+    let total: u64 = wheel
+        .intervals()
+        .skip(intervals - WINDOW)
+        .map(|interval| interval.as_micros())
+        .sum();
+    let plain = 6.0 / (total as f64 / WINDOW as f64 / 1e6) / 6.0;
+
+    let windowed = wheel.rpm_windowed(WINDOW).unwrap();
+    assert!(plain < windowed, "{plain} {windowed}");
+    assert!(windowed < generator.rpm(), "{windowed}");
+}
+
+#[test]
+fn gm_three_x_syncs_from_any_pulse_on_the_third_one() {
+    for skipped in 0..3 {
+        let mut wheel: TriggerWheel<16, GmThreeX> = TriggerWheel::new();
+        let mut generator = TickGenerator::new(GmThreeX, 1200.0);
+        for _ in 0..skipped {
+            generator.next_tick();
+        }
+
+        let mut positions = heapless::Vec::<u16, 8>::new();
+        for _ in 0..8 {
+            positions.push(generator.position()).unwrap();
+            let _ = wheel.add_tick(generator.next_tick().unwrap());
+            let expected = positions.len() >= 3;
+            assert_eq!(wheel.sync_state() == SyncState::Synced, expected);
+        }
+
+        // A pulse every 60°, 120° and 180°, at 30° per position.
+        let last = *positions.last().unwrap();
+        assert_eq!(wheel.crank_angle_degrees(), Some(last as f64 * 30.0));
+        assert!((wheel.rpm().unwrap() - 1200.0).abs() < 1.0);
+    }
+}
+
+#[test]
+fn gm_three_x_loses_sync_on_a_missing_pulse() {
+    let mut wheel: TriggerWheel<16, GmThreeX> = TriggerWheel::new();
+    let mut generator = TickGenerator::new(GmThreeX, 1200.0);
+    run(&mut wheel, &mut generator, 6);
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+
+    // The pulse at position 0 is lost, 240° after 120° fits no spacing.
+    generator.next_tick();
+    let _ = wheel.add_tick(generator.next_tick().unwrap());
+    assert_eq!(wheel.sync_state(), SyncState::Searching);
+}
+
+#[test]
+fn gm_three_x_one_x_identifies_the_tdc() {
+    let mut wheel: TriggerWheel<16, GmThreeX> = TriggerWheel::new()
+        .with_decode_mode(DecodeMode::Sequential)
+        .with_firing_schedule(FiringSchedule::new(6));
+    let mut generator = TickGenerator::new(GmThreeX, 600.0);
+
+    // The cam pulse comes every other revolution, on the pulse at 180°.
+    let mut cycle_angles = heapless::Vec::<(f64, Option<f64>), 32>::new();
+    for revolution in 0..8 {
+        for _ in 0..3 {
+            let position = generator.position();
+            let _ = wheel.add_tick(generator.next_tick().unwrap());
+            if revolution % 2 == 1 && position == 6 {
+                wheel.add_cam_tick();
+            }
+            // Angle of the pulse within the cycle, the revolution after the
+            // cam pulse starting it.
+            let angle = position as f64 * 30.0 + (revolution % 2) as f64 * 360.0;
+            cycle_angles
+                .push((angle, wheel.crank_angle_degrees()))
+                .unwrap();
+        }
+    }
+
+    // Known from the first revolution after the cam pulse on.
+    let decoded = cycle_angles
+        .iter()
+        .skip_while(|(_, decoded)| decoded.is_none());
+    assert_eq!(cycle_angles.len() - decoded.clone().count(), 6);
+    for &(angle, decoded) in decoded {
+        assert_eq!(decoded, Some(angle));
+    }
+
+    // The first cylinder fires at the second pulse, the others every 120°.
+    assert_eq!(wheel.tdc_degrees(0), 60.0);
+    assert_eq!(wheel.tdc_degrees(1), 180.0);
+    assert_eq!(wheel.tdc_degrees(5), 660.0);
+}
+
+// Runs a four cylinder engine at `rpm` over 4 cycles of a 60-2 wheel, the
+// crank slowing by 10% over the power stroke of the third cylinder, with a
+// cam pulse every cycle.
+fn run_misfiring(mode: DecodeMode, rpm: f64) -> TriggerWheel<120> {
+    let mut wheel = TriggerWheel::new()
+        .with_decode_mode(mode)
+        .with_gap_ratio(2.0);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), rpm);
+    for revolution in 0..8 {
+        for _ in 0..58 {
+            let position = generator.position();
+            // The third cylinder fires at 360° of the cycle, which starts the
+            // revolution after the cam pulse.
+            let slow = revolution % 2 == 1 && position < 30;
+            generator.set_rpm(if slow { rpm * 0.9 } else { rpm });
+            let _ = wheel.add_tick(generator.next_tick().unwrap());
+            if revolution % 2 == 1 && position == 40 {
+                wheel.add_cam_tick();
+            }
+        }
+    }
+    wheel
+}
+
+#[test]
+fn the_slow_cylinder_is_reported_as_misfiring() {
+    let wheel = run_misfiring(DecodeMode::Sequential, 3000.0);
+    assert_eq!(wheel.misfire_cylinder(), Some(2));
+    let index = wheel.misfire_index().unwrap();
+    assert!((0.06..0.1).contains(&index), "{index}");
+
+    let slow = run_misfiring(DecodeMode::Sequential, MISFIRE_MIN_RPM * 0.8);
+    assert!(slow.phase().is_some());
+    assert_eq!(slow.misfire_index(), None);
+    assert_eq!(slow.misfire_cylinder(), None);
+
+    // Without the cam phase there is no telling the cylinders apart.
+    let crank_only = run_misfiring(DecodeMode::CrankOnly, 3000.0);
+    assert_eq!(crank_only.misfire_index(), None);
+    assert_eq!(crank_only.misfire_cylinder(), None);
+}
+
+// Checks every field of the snapshot at `now` against its accessor.
+fn assert_status_matches<const N: usize>(wheel: &TriggerWheel<N>, now: Instant) {
+    let status = wheel.status(now);
+    assert_eq!(status.sync, wheel.sync_state());
+    assert_eq!(status.phase, wheel.phase());
+    assert_eq!(status.ticks_since_gap, wheel.ticks_since_gap());
+    assert_eq!(status.time_since_gap, wheel.time_since_gap(now));
+    assert_eq!(status.crank_angle, wheel.crank_angle_degrees());
+    assert_eq!(status.angle, wheel.angle_at(now));
+    assert_eq!(status.angle_uncertainty, wheel.angle_uncertainty_deg(now));
+    assert_eq!(status.rpm, wheel.rpm());
+    assert_eq!(status.rpm_filtered, wheel.rpm_filtered());
+    assert_eq!(status.misfire_index, wheel.misfire_index());
+    assert_eq!(status.dropouts, wheel.dropouts());
+    assert_eq!(status.last_dropout, wheel.last_dropout());
+    assert_eq!(status.implausible_ticks, wheel.implausible_ticks());
+}
+
+#[test]
+fn the_status_matches_the_accessors() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    run(&mut wheel, &mut generator, 20);
+    let now = wheel.latest_tick().unwrap().to_instant(mhz(1)) + Duration::from_micros(100);
+    let status = wheel.status(now);
+    assert_eq!(status.sync, SyncState::Searching);
+    assert!(status.rpm.is_some());
+    assert_eq!(status.crank_angle, None);
+    assert_status_matches(&wheel, now);
+
+    let wheel = run_misfiring(DecodeMode::Sequential, 3000.0);
+    let now = wheel.latest_tick().unwrap().to_instant(mhz(1)) + Duration::from_micros(100);
+    let status = wheel.status(now);
+    assert_eq!(status.sync, SyncState::Synced);
+    assert!(status.phase.is_some());
+    assert!(status.angle.is_some());
+    assert!(status.angle_uncertainty.is_some());
+    assert!(status.misfire_index.is_some());
+    assert_status_matches(&wheel, now);
+}
+
+#[test]
+fn a_ridden_through_dropout_keeps_the_positions_of_the_history() {
+    let mut wheel: TriggerWheel<64> = TriggerWheel::new().with_gap_ratio(2.0);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    run(&mut wheel, &mut generator, 2 * 58 + 20);
+    assert_eq!(generator.position(), 20);
+
+    for _ in 0..3 {
+        generator.next_tick();
+    }
+    run(&mut wheel, &mut generator, 1);
+    assert_eq!(wheel.dropouts(), 1);
+    assert_eq!(wheel.last_dropout(), Some(3));
+    assert_eq!(wheel.crank_angle_degrees().map(|a| a % 360.0), Some(138.0));
+
+    run(&mut wheel, &mut generator, 10);
+    let stats = wheel.interval_stats().unwrap();
+    assert!(
+        stats.max.as_micros() <= 340,
+        "max {}",
+        stats.max.as_micros()
+    );
+
+    // 3000 RPM are 18000 °/s, over normal teeth, the gap and the dropout.
+    for (position, velocity) in wheel.tooth_velocities() {
+        assert!(position.is_some());
+        assert!(
+            (velocity - 18000.0).abs() < 180.0,
+            "{} °/s at {:?}",
+            velocity,
+            position
+        );
+    }
+}
+
+#[test]
+fn a_dropout_right_after_the_gap_is_not_taken_for_the_gap() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    run(&mut wheel, &mut generator, 2 * 58);
+    assert_eq!(generator.position(), 0);
+
+    generator.next_tick();
+    run(&mut wheel, &mut generator, 1);
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+    assert_eq!(wheel.dropouts(), 1);
+    assert_eq!(wheel.last_dropout(), Some(1));
+    assert_eq!(wheel.crank_angle_degrees().map(|a| a % 360.0), Some(6.0));
+
+    run(&mut wheel, &mut generator, 58);
+    assert_eq!(wheel.sync_state(), SyncState::Synced);
+    assert_eq!(wheel.crank_angle_degrees().map(|a| a % 360.0), Some(6.0));
+}
+
+#[test]
+fn the_extrapolated_angle_stays_monotonic_across_the_gap() {
+    let mut wheel: TriggerWheel<120> = TriggerWheel::new().with_gap_ratio(2.0);
+    let mut generator = TickGenerator::new(SixtyMinusTwo::default(), 3000.0);
+    let mut last = Instant::from_ticks(0);
+    for _ in 0..58 + 50 {
+        last = generator.next_instant().unwrap();
+        let _ = wheel.add_tick_at(last);
+    }
+
+    // From tooth 49 over the gap to tooth 5 of the next revolution.
+    let mut previous = wheel.crank_angle_degrees().unwrap();
+    let mut revolutions = 0.0;
+    for _ in 0..14 {
+        let next = generator.next_instant().unwrap();
+        if generator.position() == 1 {
+            // Tooth 57 to tooth 0, waiting over the missing teeth.
+            let expected = wheel.next_tooth_at().unwrap();
+            assert!(expected.as_micros().abs_diff(next.as_micros()) <= 20);
+        }
+
+        let mut now = last;
+        while now < next + Duration::from_micros(100) {
+            let angle = wheel.angle_at(now).unwrap();
+            if angle + 180.0 < previous % 360.0 {
+                revolutions += 360.0;
+            }
+            let angle = angle + revolutions;
+            assert!(angle >= previous, "{angle} after {previous}");
+            // Held at the next tooth while it is late.
+            assert!(angle - previous < 20.0, "{angle} after {previous}");
+            previous = angle;
+            now += Duration::from_micros(10);
+        }
+
+        wheel.add_tick_at(next).unwrap();
+        last = next;
+    }
+    assert_eq!(wheel.crank_angle_degrees(), Some(30.0));
+    assert_eq!(previous, 390.0);
+}